@@ -44,6 +44,7 @@ impl BlockQueryData {
                 txn: self.raw_block.block.0[i].clone(),
                 proofs: self.raw_block.proofs[i].clone(),
                 memos: self.raw_block.memos[i].clone(),
+                memo: None,
             },
             block_id: self.block_id,
             txn_id: i as u64,
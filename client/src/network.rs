@@ -128,7 +128,7 @@ impl<'a> KeystoreBackend<'a, EspressoLedger> for NetworkBackend<'a> {
                 .0,
             freeze: snapshot
                 .state
-                .chain
+                .chain()
                 .verif_crs
                 .freeze
                 .iter()
@@ -146,7 +146,7 @@ impl<'a> KeystoreBackend<'a, EspressoLedger> for NetworkBackend<'a> {
                 .collect::<Result<_, _>>()?,
             xfr: snapshot
                 .state
-                .chain
+                .chain()
                 .verif_crs
                 .xfr
                 .iter()
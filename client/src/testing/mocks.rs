@@ -71,7 +71,7 @@ impl<'a> MockNetwork<'a, EspressoLedger> for MockEspressoNetwork<'a> {
 
     fn submit(&mut self, block: ElaboratedBlock) -> Result<usize, KeystoreError<EspressoLedger>> {
         match self.validator.validate_and_apply(
-            &(self.validator.prev_commit_time + 1),
+            &self.validator.next_time(),
             block.parent_state,
             block.block.clone(),
             block.proofs.clone(),
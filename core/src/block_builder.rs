@@ -0,0 +1,134 @@
+// Copyright (c) 2022 Espresso Systems (espressosys.com)
+// This file is part of the Espresso library.
+
+//! Incremental construction of blocks subject to size limits.
+
+use crate::state::{
+    state_comm::LedgerStateCommitment, ElaboratedBlock, ElaboratedTransaction, ValidationError,
+};
+use hotshot::traits::Block;
+
+/// Builds an [ElaboratedBlock] one transaction at a time.
+///
+/// A [BlockBuilder] enforces a limit on the number of transactions in the block and on the total
+/// number of nullifiers they spend, in addition to the usual check that no two transactions in the
+/// block spend the same nullifier. It does not validate transactions against a ledger state; that
+/// happens when the block is applied.
+#[derive(Clone, Debug)]
+pub struct BlockBuilder {
+    block: ElaboratedBlock,
+    num_nullifiers: usize,
+    max_transactions: usize,
+    max_nullifiers: usize,
+}
+
+impl BlockBuilder {
+    /// Start building an empty block to be applied to the state with commitment `parent_state`.
+    pub fn new(
+        parent_state: LedgerStateCommitment,
+        max_transactions: usize,
+        max_nullifiers: usize,
+    ) -> Self {
+        Self {
+            block: ElaboratedBlock::new(parent_state),
+            num_nullifiers: 0,
+            max_transactions,
+            max_nullifiers,
+        }
+    }
+
+    /// Add a transaction to the block.
+    ///
+    /// If the transaction cannot be added, the block is left unchanged.
+    ///
+    /// # Errors
+    /// - [ValidationError::BlockTooLarge] if the block already has the maximum number of
+    ///   transactions
+    /// - [ValidationError::TooManyNullifiers] if adding `txn` would exceed the maximum number of
+    ///   nullifiers
+    /// - [ValidationError::ConflictingNullifiers] if `txn` spends a nullifier which is already
+    ///   spent by another transaction in the block
+    pub fn add(&mut self, txn: ElaboratedTransaction) -> Result<&mut Self, ValidationError> {
+        if self.block.len() >= self.max_transactions {
+            return Err(ValidationError::BlockTooLarge {
+                num_txns: self.block.len() + 1,
+                max: self.max_transactions,
+            });
+        }
+        let num_nullifiers = self.num_nullifiers + txn.input_count();
+        if num_nullifiers > self.max_nullifiers {
+            return Err(ValidationError::TooManyNullifiers {
+                num_nullifiers,
+                max: self.max_nullifiers,
+            });
+        }
+        self.block = self.block.add_transaction_raw(&txn)?;
+        self.num_nullifiers = num_nullifiers;
+        Ok(self)
+    }
+
+    /// Whether the block has reached one of its limits.
+    ///
+    /// A full block cannot accept any more transactions, except that a transaction with no
+    /// nullifiers can still be added if only the nullifier limit has been reached.
+    pub fn is_full(&self) -> bool {
+        self.block.len() >= self.max_transactions || self.num_nullifiers >= self.max_nullifiers
+    }
+
+    /// The number of transactions added so far.
+    pub fn len(&self) -> usize {
+        self.block.len()
+    }
+
+    /// Whether no transactions have been added yet.
+    pub fn is_empty(&self) -> bool {
+        self.block.is_empty()
+    }
+
+    /// Finish building the block.
+    pub fn build(self) -> ElaboratedBlock {
+        self.block
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::ValidatorState;
+
+    #[test]
+    fn test_block_builder_max_transactions() {
+        let parent = ValidatorState::default().commit();
+        let mut builder = BlockBuilder::new(parent, 2, 10);
+        assert!(builder.is_empty());
+        assert!(!builder.is_full());
+
+        builder
+            .add(ElaboratedTransaction::default())
+            .unwrap()
+            .add(ElaboratedTransaction::default())
+            .unwrap();
+        assert_eq!(builder.len(), 2);
+        assert!(builder.is_full());
+        assert!(matches!(
+            builder.add(ElaboratedTransaction::default()),
+            Err(ValidationError::BlockTooLarge {
+                num_txns: 3,
+                max: 2
+            })
+        ));
+
+        let block = builder.build();
+        assert_eq!(block.len(), 2);
+        assert_eq!(block.parent_state, parent);
+    }
+
+    #[test]
+    fn test_block_builder_no_nullifiers() {
+        // Transactions without nullifiers can always be added, up to the transaction limit.
+        let mut builder = BlockBuilder::new(ValidatorState::default().commit(), 10, 0);
+        assert!(builder.is_full());
+        builder.add(ElaboratedTransaction::default()).unwrap();
+        assert_eq!(builder.len(), 1);
+    }
+}
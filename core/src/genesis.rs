@@ -87,6 +87,17 @@ impl GenesisNote {
             stake_table,
         }
     }
+
+    /// A genesis transaction for the default chain, with no faucet records and an empty stake
+    /// table.
+    pub fn empty() -> Self {
+        Self::new(
+            ChainVariables::default(),
+            Default::default(),
+            Default::default(),
+        )
+    }
+
     pub fn output_len(&self) -> usize {
         self.faucet_records.len()
     }
@@ -180,6 +180,7 @@ impl traits::Transaction for ElaboratedTransaction {
             txn: EspressoTransaction::CAP(note),
             proofs: EspressoTxnHelperProofs::CAP(proofs),
             memos: None,
+            memo: None,
         }
     }
 
@@ -259,6 +260,7 @@ impl traits::Block for ElaboratedBlock {
                 txn: txn.clone(),
                 proofs: proofs.clone(),
                 memos: memos.clone(),
+                memo: None,
             })
             .collect()
     }
@@ -2,12 +2,14 @@
 // Copyright (c) 2022 Espresso Systems (espressosys.com)
 // This file is part of the Espresso library.
 
+pub mod block_builder;
 pub mod genesis;
 pub mod kv_merkle_tree;
 pub mod ledger;
 pub mod lw_persistence;
 pub mod merkle_tree;
 pub mod reward;
+pub mod serde_helpers;
 pub mod set_merkle_tree;
 pub mod stake_table;
 pub mod state;
@@ -10,12 +10,73 @@ use hotshot::{data::Leaf, types::EventType};
 use async_std::task::{spawn, JoinHandle};
 use core::fmt::Debug;
 use futures::stream::{Stream, StreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
 use std::path::{Path, PathBuf};
 
+/// Errors from storing or loading an [LWPersistence].
+#[derive(Debug, Snafu)]
+pub enum LWPersistenceError {
+    #[snafu(display("{}", source))]
+    Persistence { source: PersistenceError },
+    #[snafu(display("failed to (de)serialize persisted state: {}", source))]
+    Serialization { source: bincode::Error },
+    /// The persisted bytes do not match the checksum stored with them.
+    #[snafu(display(
+        "persisted state is corrupted: expected checksum {:#010x}, got {:#010x}",
+        expected,
+        actual
+    ))]
+    CorruptedState { expected: u32, actual: u32 },
+}
+
+impl From<PersistenceError> for LWPersistenceError {
+    fn from(source: PersistenceError) -> Self {
+        Self::Persistence { source }
+    }
+}
+
+/// A serialized resource together with a CRC-32 checksum of its bytes.
+///
+/// Deserialization alone does not catch all corruption of persisted data: a flipped bit in a hash
+/// or an amount still deserializes successfully. Checking the checksum on load catches this before
+/// the corrupted state is used.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Checksummed {
+    checksum: u32,
+    bytes: Vec<u8>,
+}
+
+impl Checksummed {
+    fn new<T: Serialize>(value: &T) -> Result<Self, LWPersistenceError> {
+        let bytes = bincode::serialize(value).context(SerializationSnafu)?;
+        Ok(Self {
+            checksum: crc32fast::hash(&bytes),
+            bytes,
+        })
+    }
+
+    fn open<T: DeserializeOwned>(&self) -> Result<T, LWPersistenceError> {
+        let actual = crc32fast::hash(&self.bytes);
+        if actual != self.checksum {
+            return Err(LWPersistenceError::CorruptedState {
+                expected: self.checksum,
+                actual,
+            });
+        }
+        bincode::deserialize(&self.bytes).context(SerializationSnafu)
+    }
+}
+
+/// Light-weight persistence of the latest decided leaf.
+///
+/// The leaf is stored as a [Checksummed] record. Stores written before checksums were added hold
+/// the bare leaf and cannot be loaded by this version; such stores must be reset (for example,
+/// with the validator's `--reset-store-state` option).
 #[must_use]
 pub struct LWPersistence {
     atomic_store: AtomicStore,
-    leaf_snapshot: RollingLog<BincodeLoadStore<Leaf<ValidatorState>>>,
+    leaf_snapshot: RollingLog<BincodeLoadStore<Checksummed>>,
 }
 
 const LEAF_STORAGE_COUNT: u32 = 1;
@@ -51,19 +112,26 @@ impl LWPersistence {
         })
     }
 
-    pub fn load_latest_leaf(&self) -> Result<Leaf<ValidatorState>, PersistenceError> {
-        self.leaf_snapshot.load_latest()
+    /// Load the most recently stored leaf.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [LWPersistenceError::CorruptedState] if the stored leaf does not match its
+    /// checksum.
+    pub fn load_latest_leaf(&self) -> Result<Leaf<ValidatorState>, LWPersistenceError> {
+        self.leaf_snapshot.load_latest()?.open()
     }
 
-    fn store_latest_leaf(&mut self, leaf: &Leaf<ValidatorState>) -> Result<(), PersistenceError> {
-        self.leaf_snapshot.store_resource(leaf)?;
+    fn store_latest_leaf(&mut self, leaf: &Leaf<ValidatorState>) -> Result<(), LWPersistenceError> {
+        self.leaf_snapshot
+            .store_resource(&Checksummed::new(leaf)?)?;
         self.leaf_snapshot.commit_version()?;
         if let Err(err) = self.leaf_snapshot.prune_file_entries() {
             // Pruning the file entries is an optimization, not a failure that should stop us from
             // committing. Log the error and move along.
             tracing::warn!("failed to prune file entries: {}", err);
         }
-        self.atomic_store.commit_version()
+        Ok(self.atomic_store.commit_version()?)
     }
 
     pub fn launch(
@@ -90,3 +158,35 @@ impl Debug for LWPersistence {
         f.debug_struct("LWPersistence").finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksummed_round_trip() {
+        let value = vec![1u64, 2, 3];
+        let checksummed = Checksummed::new(&value).unwrap();
+        assert_eq!(checksummed.open::<Vec<u64>>().unwrap(), value);
+    }
+
+    #[test]
+    fn test_checksummed_corruption() {
+        let mut checksummed = Checksummed::new(&vec![1u64, 2, 3]).unwrap();
+        // Flip a bit in the data. The result still deserializes, but no longer matches the
+        // checksum.
+        checksummed.bytes[8] ^= 1;
+        assert!(matches!(
+            checksummed.open::<Vec<u64>>(),
+            Err(LWPersistenceError::CorruptedState { .. })
+        ));
+
+        // Corrupting the checksum itself is also detected.
+        let mut checksummed = Checksummed::new(&vec![1u64, 2, 3]).unwrap();
+        checksummed.checksum ^= 1;
+        assert!(matches!(
+            checksummed.open::<Vec<u64>>(),
+            Err(LWPersistenceError::CorruptedState { .. })
+        ));
+    }
+}
@@ -0,0 +1,58 @@
+// Copyright (c) 2022 Espresso Systems (espressosys.com)
+// This file is part of the Espresso library.
+
+//! Serde adapters for values which cannot be serialized themselves.
+//!
+//! Each module in this file can be used with `#[serde(with = "...")]` on a field of type
+//! `Result<T, String>`. The `Ok` value is serialized as a string, using its [Display] or [Debug]
+//! representation. Since the original value cannot be recovered from the string, deserializing
+//! always produces the `Err` variant, containing the string. This is useful for errors wrapping
+//! foreign error types which do not implement [Serialize](serde::Serialize): the error survives
+//! serialization, at least in string form.
+//!
+//! [Display]: std::fmt::Display
+//! [Debug]: std::fmt::Debug
+
+/// Serialize a `Result<T, String>` using the [Display](std::fmt::Display) implementation of `T`.
+pub mod ser_display {
+    use serde::de::{Deserialize, Deserializer};
+    use serde::ser::{Serialize, Serializer};
+    use std::fmt::Display;
+
+    pub fn serialize<S: Serializer, T: Display>(
+        v: &Result<T, String>,
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        let string = match v {
+            Ok(v) => format!("{}", v),
+            Err(string) => string.clone(),
+        };
+        Serialize::serialize(&string, s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T>(d: D) -> Result<Result<T, String>, D::Error> {
+        Ok(Err(Deserialize::deserialize(d)?))
+    }
+}
+
+/// Serialize a `Result<T, String>` using the [Debug](std::fmt::Debug) implementation of `T`.
+pub mod ser_debug {
+    use serde::de::{Deserialize, Deserializer};
+    use serde::ser::{Serialize, Serializer};
+    use std::fmt::Debug;
+
+    pub fn serialize<S: Serializer, T: Debug>(
+        v: &Result<T, String>,
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        let string = match v {
+            Ok(v) => format!("{:?}", v),
+            Err(string) => string.clone(),
+        };
+        Serialize::serialize(&string, s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T>(d: D) -> Result<Result<T, String>, D::Error> {
+        Ok(Err(Deserialize::deserialize(d)?))
+    }
+}
@@ -277,16 +277,36 @@ impl SetMerkleProof {
         }
 
         if &running_hash == root {
-            Ok(match &self.terminal_node {
-                SetMerkleTerminalNode::EmptySubtree {} => false,
-                SetMerkleTerminalNode::Leaf {
-                    elem: leaf_elem, ..
-                } => leaf_elem == &elem,
-            })
+            Ok(self.is_membership(elem))
         } else {
             Err(running_hash)
         }
     }
+
+    /// Whether this is a proof that `elem` is in the set.
+    ///
+    /// The direction of a proof is determined by its terminal node: a leaf containing `elem` proves
+    /// membership, while an empty subtree or a leaf containing a different element proves
+    /// non-membership. Since the terminal node alone does not record which element the proof is
+    /// for, the element must be given.
+    ///
+    /// This does not check that the proof is valid; use [SetMerkleProof::check] for that.
+    pub fn is_membership(&self, elem: Nullifier) -> bool {
+        match &self.terminal_node {
+            SetMerkleTerminalNode::EmptySubtree {} => false,
+            SetMerkleTerminalNode::Leaf {
+                elem: leaf_elem, ..
+            } => leaf_elem == &elem,
+        }
+    }
+
+    /// Whether this is a proof that `elem` is not in the set.
+    ///
+    /// This is the opposite of [SetMerkleProof::is_membership], and likewise does not check that the
+    /// proof is valid.
+    pub fn is_non_membership(&self, elem: Nullifier) -> bool {
+        !self.is_membership(elem)
+    }
 }
 
 impl SetMerkleTree {
@@ -768,4 +788,144 @@ mod tests {
             .tests(10)
             .quickcheck(test_merkle_tree_set as fn(Vec<_>, Vec<_>) -> ());
     }
+    fn random_nullifiers(prng: &mut ChaChaRng, n: usize) -> Vec<Nullifier> {
+        (0..n).map(|_| Nullifier::random_for_test(prng)).collect()
+    }
+
+    /// Nullifiers paired with non-membership proofs relative to `t`.
+    fn with_proofs(t: &SetMerkleTree, nulls: &[Nullifier]) -> Vec<(Nullifier, SetMerkleProof)> {
+        nulls
+            .iter()
+            .map(|n| {
+                let (in_set, pf) = t.contains(*n).unwrap();
+                assert!(!in_set);
+                (*n, pf)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lw_multi_insert_empty_tree() {
+        let mut prng = ChaChaRng::from_seed([0x1u8; 32]);
+        let t = SetMerkleTree::default();
+
+        // Inserting nothing leaves the root unchanged.
+        let (root, proofs) = set_merkle_lw_multi_insert(vec![], t.hash()).unwrap();
+        assert_eq!(root, t.hash());
+        assert!(proofs.is_empty());
+
+        // Inserting into an empty tree yields the same root as the full tree.
+        let nulls = random_nullifiers(&mut prng, 3);
+        let (root, proofs) = set_merkle_lw_multi_insert(with_proofs(&t, &nulls), t.hash()).unwrap();
+        let mut full = t;
+        for n in &nulls {
+            full.insert(*n).unwrap();
+        }
+        assert_eq!(root, full.hash());
+        for (n, pf) in nulls.iter().zip(proofs) {
+            assert!(pf.check(*n, &root).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_lw_multi_insert_duplicate() {
+        let mut prng = ChaChaRng::from_seed([0x2u8; 32]);
+        let t = SetMerkleTree::default();
+        let n = Nullifier::random_for_test(&mut prng);
+        let inserts = with_proofs(&t, &[n]);
+
+        // Inserting the same nullifier twice in one batch is the same as inserting it once. It is
+        // up to the caller to reject double spends before updating the set.
+        let (once, _) = set_merkle_lw_multi_insert(inserts.clone(), t.hash()).unwrap();
+        let (twice, proofs) = set_merkle_lw_multi_insert(
+            inserts
+                .iter()
+                .cloned()
+                .chain(inserts.iter().cloned())
+                .collect(),
+            t.hash(),
+        )
+        .unwrap();
+        assert_eq!(once, twice);
+        assert_eq!(proofs.len(), 2);
+        for pf in proofs {
+            assert!(pf.check(n, &twice).unwrap());
+        }
+
+        // Once the nullifier is in the set, its old non-membership proof is no longer valid.
+        assert!(set_merkle_lw_multi_insert(inserts, once).is_err());
+    }
+
+    #[test]
+    fn test_lw_multi_insert_wrong_proof() {
+        let mut prng = ChaChaRng::from_seed([0x3u8; 32]);
+        let mut t = SetMerkleTree::default();
+        for n in random_nullifiers(&mut prng, 5) {
+            t.insert(n).unwrap();
+        }
+        let nulls = random_nullifiers(&mut prng, 2);
+        let inserts = with_proofs(&t, &nulls);
+
+        // A proof with a corrupted path.
+        let mut bad_proof = inserts[1].1.clone();
+        bad_proof.path[0] = set_hash::leaf_hash(Nullifier::random_for_test(&mut prng));
+        assert!(set_merkle_lw_multi_insert(vec![(nulls[1], bad_proof.clone())], t.hash()).is_err());
+
+        // A proof relative to a different root.
+        let mut other = t.clone();
+        other.insert(Nullifier::random_for_test(&mut prng)).unwrap();
+        assert!(set_merkle_lw_multi_insert(inserts.clone(), other.hash()).is_err());
+
+        // A valid proof followed by an invalid one fails the whole batch.
+        assert!(set_merkle_lw_multi_insert(
+            vec![inserts[0].clone(), (nulls[1], bad_proof)],
+            t.hash()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_lw_multi_insert_deterministic() {
+        let mut prng = ChaChaRng::from_seed([0x4u8; 32]);
+        let mut t = SetMerkleTree::default();
+        for n in random_nullifiers(&mut prng, 5) {
+            t.insert(n).unwrap();
+        }
+        let inserts = with_proofs(&t, &random_nullifiers(&mut prng, 4));
+
+        let (root1, proofs1) = set_merkle_lw_multi_insert(inserts.clone(), t.hash()).unwrap();
+        let (root2, proofs2) = set_merkle_lw_multi_insert(inserts.clone(), t.hash()).unwrap();
+        assert_eq!(root1, root2);
+        assert_eq!(proofs1, proofs2);
+
+        // The root only depends on the set of nullifiers, not on the order they were inserted in.
+        let (root3, _) =
+            set_merkle_lw_multi_insert(inserts.into_iter().rev().collect(), t.hash()).unwrap();
+        assert_eq!(root1, root3);
+    }
+
+    #[test]
+    fn test_proof_direction() {
+        let mut prng = ChaChaRng::from_seed([0x5u8; 32]);
+        let mut t = SetMerkleTree::default();
+        let nulls = random_nullifiers(&mut prng, 3);
+        t.insert(nulls[0]).unwrap();
+
+        // A proof ending in a leaf, for the element in that leaf.
+        let (in_set, pf) = t.contains(nulls[0]).unwrap();
+        assert!(in_set);
+        assert!(pf.is_membership(nulls[0]));
+        assert!(!pf.is_non_membership(nulls[0]));
+
+        // A proof ending in a leaf for a different element, in a tree with one element.
+        let (in_set, pf) = t.contains(nulls[1]).unwrap();
+        assert!(!in_set);
+        assert!(pf.is_non_membership(nulls[1]));
+
+        // A proof ending in an empty subtree.
+        let (in_set, pf) = SetMerkleTree::default().contains(nulls[2]).unwrap();
+        assert!(!in_set);
+        assert!(!pf.is_membership(nulls[2]));
+        assert!(pf.is_non_membership(nulls[2]));
+    }
 }
@@ -28,28 +28,33 @@ use crate::stake_table::{
     StakeTableSetHistory, StakeTableSetMT,
 };
 
-use crate::state::state_comm::CommittableAmount;
+use crate::serde_helpers::ser_display;
+use crate::state::state_comm::{CommittableAmount, CommittableVerifierKeySet};
 use crate::universal_params::{MERKLE_HEIGHT, VERIF_CRS};
 use arbitrary::{Arbitrary, Unstructured};
 use ark_serialize::*;
 use canonical::deserialize_canonical_bytes;
 use canonical::CanonicalBytes;
 use commit::{Commitment, Committable};
-use core::fmt::Debug;
+use core::cmp::Ordering;
+use core::fmt::{self, Debug, Display, Formatter};
 use derive_more::{AsRef, From, Into};
 use hotshot::traits::{Block as ConsensusBlock, State as ConsensusState};
 use jf_cap::{
-    errors::TxnApiError, structs::Nullifier, txn_batch_verify, MerkleCommitment, MerkleFrontier,
-    MerkleLeafProof, MerkleTree, NodeValue, TransactionNote,
+    errors::TxnApiError,
+    structs::{Nullifier, RecordCommitment},
+    txn_batch_verify, MerkleCommitment, MerkleFrontier, MerkleLeafProof, MerkleTree, NodeValue,
+    TransactionNote,
 };
 use jf_primitives::merkle_tree::FilledMTBuilder;
 use jf_utils::tagged_blob;
-use key_set::VerifierKeySet;
+use key_set::{ProverKeySet, SizedKey, VerifierKeySet};
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 use sha3::digest::Update;
 use sha3::Digest;
 use snafu::Snafu;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{hash_map::Entry, BTreeSet, HashMap, HashSet, VecDeque};
 use std::hash::{Hash, Hasher};
 use std::io::Read;
 use std::iter::once;
@@ -195,21 +200,63 @@ impl CanonicalDeserialize for EspressoTxnHelperProofs {
 /// equal to the hash of the record's nullifier and an empty value,
 /// which demonstrates that the unspent record is not in the nullifier
 /// set rooted at the path's root hash.
-#[derive(
-    Debug,
-    Clone,
-    CanonicalSerialize,
-    CanonicalDeserialize,
-    PartialEq,
-    Eq,
-    Hash,
-    Serialize,
-    Deserialize,
-)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ElaboratedTransaction {
     pub txn: EspressoTransaction,
     pub proofs: EspressoTxnHelperProofs,
     pub memos: Option<(Vec<ReceiverMemo>, Signature)>,
+    /// Opaque off-chain metadata attached by the client managing this transaction.
+    ///
+    /// The memo is not interpreted or validated by the consensus layer, and it is not stored in
+    /// blocks: a transaction taken out of a block never has a memo. For the same reason, the memo
+    /// is not part of the transaction's commitment or its canonical serialization, so a
+    /// transaction has the same identity whether or not it has a memo.
+    #[serde(default)]
+    pub memo: Option<Vec<u8>>,
+}
+
+/// The canonical encoding of an [ElaboratedTransaction] omits the off-chain
+/// [memo](ElaboratedTransaction::memo).
+impl CanonicalSerialize for ElaboratedTransaction {
+    fn serialize<W: Write>(&self, mut writer: W) -> Result<(), SerializationError> {
+        CanonicalSerialize::serialize(&self.txn, &mut writer)?;
+        CanonicalSerialize::serialize(&self.proofs, &mut writer)?;
+        CanonicalSerialize::serialize(&self.memos, &mut writer)
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.txn.serialized_size() + self.proofs.serialized_size() + self.memos.serialized_size()
+    }
+}
+
+impl CanonicalDeserialize for ElaboratedTransaction {
+    fn deserialize<R>(mut r: R) -> Result<Self, ark_serialize::SerializationError>
+    where
+        R: ark_serialize::Read,
+    {
+        Ok(Self {
+            txn: CanonicalDeserialize::deserialize(&mut r)?,
+            proofs: CanonicalDeserialize::deserialize(&mut r)?,
+            memos: CanonicalDeserialize::deserialize(&mut r)?,
+            memo: None,
+        })
+    }
+}
+
+/// A placeholder transaction: an empty genesis transaction for the default chain.
+///
+/// [TransactionNote] has no default value, so this uses a genesis note with no records and an
+/// empty stake table. Like an empty [Block], it is intended for test fixtures and placeholders,
+/// and will not validate against any state but the default one.
+impl Default for ElaboratedTransaction {
+    fn default() -> Self {
+        Self {
+            txn: EspressoTransaction::Genesis(GenesisNote::empty()),
+            proofs: EspressoTxnHelperProofs::Genesis,
+            memos: None,
+            memo: None,
+        }
+    }
 }
 
 impl ElaboratedTransaction {
@@ -217,6 +264,70 @@ impl ElaboratedTransaction {
         self.txn.is_genesis()
     }
 
+    /// The number of inputs (nullifiers) of this transaction.
+    ///
+    /// Together with [output_count](Self::output_count), this determines which proving and
+    /// verifying keys apply to the transaction.
+    pub fn input_count(&self) -> usize {
+        self.txn.input_len()
+    }
+
+    /// The number of output records created by this transaction.
+    pub fn output_count(&self) -> usize {
+        self.txn.output_len()
+    }
+
+    /// The nullifiers of the records spent by this transaction.
+    pub fn nullifiers(&self) -> Vec<Nullifier> {
+        self.txn.input_nullifiers()
+    }
+
+    /// The commitments to the records created by this transaction.
+    pub fn output_commitments(&self) -> Vec<RecordCommitment> {
+        self.txn.output_commitments()
+    }
+
+    /// Check the nullifier proofs of this transaction against a nullifier set root hash.
+    ///
+    /// This succeeds if this is a CAP transaction with one proof for each of its nullifiers, each
+    /// of which proves that the nullifier is not in the set with root hash `nullifiers_root`. It
+    /// does not check any other part of the transaction. Genesis and reward transactions have no
+    /// nullifier proofs, and always succeed as long as their helper proofs are of the right kind.
+    ///
+    /// # Errors
+    /// - [ValidationError::InconsistentHelperProofs]
+    /// - [ValidationError::ProofCountMismatch], with no `txn_index`, since the transaction is not
+    ///   checked as part of a block
+    /// - [ValidationError::BadNullifierProof]
+    /// - [ValidationError::NullifierAlreadyExists]
+    pub fn verify_proofs(&self, nullifiers_root: &set_hash::Hash) -> Result<(), ValidationError> {
+        match (&self.txn, &self.proofs) {
+            (EspressoTransaction::CAP(note), EspressoTxnHelperProofs::CAP(proofs)) => {
+                let nullifiers = note.nullifiers();
+                if nullifiers.len() != proofs.len() {
+                    return Err(ValidationError::ProofCountMismatch {
+                        txn_index: None,
+                        expected: nullifiers.len(),
+                        got: proofs.len(),
+                    });
+                }
+                for (nullifier, proof) in nullifiers.into_iter().zip(proofs) {
+                    match proof.check(nullifier, nullifiers_root) {
+                        Ok(false) => {}
+                        Ok(true) => {
+                            return Err(ValidationError::NullifierAlreadyExists { nullifier })
+                        }
+                        Err(_) => return Err(ValidationError::BadNullifierProof {}),
+                    }
+                }
+                Ok(())
+            }
+            (EspressoTransaction::Reward(_), EspressoTxnHelperProofs::Reward(_))
+            | (EspressoTransaction::Genesis(_), EspressoTxnHelperProofs::Genesis) => Ok(()),
+            _ => Err(ValidationError::InconsistentHelperProofs),
+        }
+    }
+
     fn build_commitment(
         txn: &EspressoTransaction,
         proofs: &EspressoTxnHelperProofs,
@@ -237,19 +348,92 @@ impl ElaboratedTransaction {
 /// it is possible to drop individual invalid transactions.
 #[ser_test]
 #[derive(
-    Default,
-    Debug,
-    Clone,
-    CanonicalSerialize,
-    CanonicalDeserialize,
-    Serialize,
-    Deserialize,
-    PartialEq,
-    Eq,
-    Hash,
+    Default, Debug, Clone, CanonicalSerialize, CanonicalDeserialize, Serialize, Deserialize,
 )]
 pub struct Block(pub Vec<EspressoTransaction>);
 
+/// Blocks are equal if their canonical serializations are equal.
+///
+/// This makes block equality independent of the equality semantics of the transaction types.
+impl PartialEq for Block {
+    fn eq(&self, other: &Self) -> bool {
+        canonical::serialize(self).unwrap() == canonical::serialize(other).unwrap()
+    }
+}
+
+impl Eq for Block {}
+
+impl Hash for Block {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        canonical::serialize(self).unwrap().hash(state);
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref EMPTY_BLOCK_COMMITMENT: Commitment<Block> = Block::default().commit();
+}
+
+impl Block {
+    /// The commitment to the empty block.
+    ///
+    /// This is the [prev_block](ValidatorState::prev_block) of a state which has had no blocks
+    /// applied to it. It is computed once and cached.
+    pub fn empty_commitment() -> Commitment<Block> {
+        *EMPTY_BLOCK_COMMITMENT
+    }
+
+    /// The number of transactions in this block.
+    pub fn transaction_count(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether this block contains no transactions.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The total number of nullifiers spent by the transactions in this block.
+    pub fn nullifier_count(&self) -> usize {
+        self.0.iter().map(|txn| txn.input_len()).sum()
+    }
+
+    /// The total number of record commitments created by the transactions in this block.
+    pub fn output_commitment_count(&self) -> usize {
+        self.0.iter().map(|txn| txn.output_len()).sum()
+    }
+
+    /// Check that `keyset` has a verifying key for every transaction in this block.
+    ///
+    /// This is much cheaper than verifying the proofs in the block, so it can be used to quickly
+    /// reject blocks containing transactions of an unsupported size. It does not verify anything
+    /// else about the block.
+    ///
+    /// # Errors
+    /// - [ValidationError::UnsupportedTransferSize] if there is no verifying key for one of the
+    ///   transfer transactions
+    /// - [ValidationError::UnsupportedFreezeSize] if there is no verifying key for one of the
+    ///   freeze transactions
+    pub fn is_valid_against_keyset(&self, keyset: &VerifierKeySet) -> Result<(), ValidationError> {
+        for txn in &self.0 {
+            if let EspressoTransaction::CAP(note) = txn {
+                if !verifier_supports_transaction(keyset, note) {
+                    let (num_inputs, num_outputs) = txn_size_class(note);
+                    return Err(match note {
+                        TransactionNote::Freeze(_) => {
+                            ValidationError::UnsupportedFreezeSize { num_inputs }
+                        }
+                        _ => ValidationError::UnsupportedTransferSize {
+                            num_inputs,
+                            num_outputs,
+                        },
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 /// A block of transactions with proofs
 ///
 /// The proofs demonstrate that the nullifiers for the transaction's
@@ -261,17 +445,7 @@ pub struct Block(pub Vec<EspressoTransaction>);
 /// can check nullifiers are not already present in the ledger without
 /// the secret information.
 #[ser_test(arbitrary)]
-#[derive(
-    Debug,
-    Clone,
-    CanonicalSerialize,
-    CanonicalDeserialize,
-    PartialEq,
-    Eq,
-    Hash,
-    Serialize,
-    Deserialize,
-)]
+#[derive(Debug, Clone, CanonicalSerialize, CanonicalDeserialize, Serialize, Deserialize)]
 pub struct ElaboratedBlock {
     /// Commitment to the state this block is intended to be appended to.
     ///
@@ -283,9 +457,59 @@ pub struct ElaboratedBlock {
     pub memos: Vec<Option<(Vec<ReceiverMemo>, Signature)>>,
 }
 
+/// Like [Block], elaborated blocks are equal if their canonical serializations are equal.
+impl PartialEq for ElaboratedBlock {
+    fn eq(&self, other: &Self) -> bool {
+        canonical::serialize(self).unwrap() == canonical::serialize(other).unwrap()
+    }
+}
+
+impl Eq for ElaboratedBlock {}
+
+impl Hash for ElaboratedBlock {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        canonical::serialize(self).unwrap().hash(state);
+    }
+}
+
+/// The kind of a CAP transaction, determined by the variant of its [TransactionNote].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TransactionKind {
+    Mint,
+    Transfer,
+    Freeze,
+}
+
+impl TransactionKind {
+    pub fn of(note: &TransactionNote) -> Self {
+        match note {
+            TransactionNote::Mint(_) => Self::Mint,
+            TransactionNote::Transfer(_) => Self::Transfer,
+            TransactionNote::Freeze(_) => Self::Freeze,
+        }
+    }
+}
+
+/// Generates blocks of between 0 and 4 [GenesisNote] transactions, each with its (empty) helper
+/// proofs and memos.
+///
+/// Generating CAP transactions requires building real zero-knowledge proofs, which is far too slow
+/// for fuzzing, so the generated blocks never contain transactions with nullifiers, and they do not
+/// exercise the matching of nullifier proofs to nullifiers. Tests which need that should build
+/// blocks with [MultiXfrTestState](crate::testing::MultiXfrTestState) instead. The blocks are
+/// structurally valid, but are not necessarily valid with respect to any state.
 impl<'a> Arbitrary<'a> for ElaboratedBlock {
     fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
-        Ok(Self::genesis(u.arbitrary()?))
+        let num_txns = u.int_in_range(0..=4)?;
+        let txns = (0..num_txns)
+            .map(|_| Ok(EspressoTransaction::Genesis(u.arbitrary()?)))
+            .collect::<arbitrary::Result<Vec<_>>>()?;
+        Ok(Self {
+            parent_state: u.arbitrary()?,
+            proofs: vec![EspressoTxnHelperProofs::Genesis; txns.len()],
+            memos: vec![None; txns.len()],
+            block: Block(txns),
+        })
     }
 }
 
@@ -299,21 +523,475 @@ impl ElaboratedBlock {
         }
     }
 
-    pub fn genesis(txn: GenesisNote) -> Self {
-        Self {
-            parent_state: ValidatorState::default().commit(),
-            block: Block(vec![EspressoTransaction::Genesis(txn)]),
-            proofs: vec![EspressoTxnHelperProofs::Genesis],
-            memos: vec![None],
-        }
+    /// An empty block with room for `n` transactions without reallocating.
+    pub fn with_capacity(parent_state: LedgerStateCommitment, n: usize) -> Self {
+        Self {
+            parent_state,
+            block: Block(Vec::with_capacity(n)),
+            proofs: Vec::with_capacity(n),
+            memos: Vec::with_capacity(n),
+        }
+    }
+
+    /// Build a block for the state with commitment `parent_state` out of a list of already
+    /// validated transactions, preserving their order.
+    ///
+    /// No checks are performed on the transactions. To check for conflicting nullifiers, use the
+    /// [TryFrom] implementation for `(LedgerStateCommitment, Vec<ElaboratedTransaction>)`.
+    pub fn from_transactions(
+        parent_state: LedgerStateCommitment,
+        txns: Vec<ElaboratedTransaction>,
+    ) -> Self {
+        let mut block = Self::with_capacity(parent_state, txns.len());
+        for ElaboratedTransaction {
+            txn, proofs, memos, ..
+        } in txns
+        {
+            block.block.0.push(txn);
+            block.proofs.push(proofs);
+            block.memos.push(memos);
+        }
+        block
+    }
+
+    pub fn genesis(txn: GenesisNote) -> Self {
+        Self {
+            parent_state: ValidatorState::default().commit(),
+            block: Block(vec![EspressoTransaction::Genesis(txn)]),
+            proofs: vec![EspressoTxnHelperProofs::Genesis],
+            memos: vec![None],
+        }
+    }
+
+    /// Build a block from a list of transactions and their helper proofs.
+    ///
+    /// Unlike constructing an [ElaboratedBlock] directly, this checks that there is exactly one set
+    /// of helper proofs for each transaction, and that each CAP transaction has exactly one
+    /// nullifier proof for each of its nullifiers. The resulting block has no memos.
+    ///
+    /// # Errors
+    /// - [ValidationError::MissingNullifierProof] if the number of proofs does not match the number
+    ///   of transactions
+    /// - [ValidationError::ProofCountMismatch]
+    /// - [ValidationError::InconsistentHelperProofs] if a transaction's helper proofs are not of the
+    ///   same kind as the transaction
+    pub fn from_raw_parts(
+        parent_state: LedgerStateCommitment,
+        block: Block,
+        proofs: Vec<EspressoTxnHelperProofs>,
+    ) -> Result<Self, ValidationError> {
+        if block.0.len() != proofs.len() {
+            return Err(ValidationError::MissingNullifierProof {});
+        }
+        for (txn_index, (txn, proofs)) in block.0.iter().zip(&proofs).enumerate() {
+            match (txn, proofs) {
+                (EspressoTransaction::CAP(note), EspressoTxnHelperProofs::CAP(proofs)) => {
+                    let expected = note.nullifiers().len();
+                    if proofs.len() != expected {
+                        return Err(ValidationError::ProofCountMismatch {
+                            txn_index: Some(txn_index),
+                            expected,
+                            got: proofs.len(),
+                        });
+                    }
+                }
+                (EspressoTransaction::Reward(_), EspressoTxnHelperProofs::Reward(_))
+                | (EspressoTransaction::Genesis(_), EspressoTxnHelperProofs::Genesis) => {}
+                _ => return Err(ValidationError::InconsistentHelperProofs),
+            }
+        }
+        Ok(Self {
+            parent_state,
+            memos: vec![None; block.0.len()],
+            block,
+            proofs,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.block.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.block.0.is_empty()
+    }
+
+    /// The sum of the fees paid by all of the transactions in this block.
+    ///
+    /// Only CAP transactions pay fees; genesis and reward transactions contribute 0.
+    pub fn total_fee(&self) -> u128 {
+        self.block
+            .0
+            .iter()
+            .map(|txn| match txn {
+                EspressoTransaction::CAP(TransactionNote::Transfer(note)) => {
+                    u128::from(note.aux_info.fee)
+                }
+                EspressoTransaction::CAP(TransactionNote::Mint(note)) => {
+                    u128::from(note.aux_info.fee)
+                }
+                EspressoTransaction::CAP(TransactionNote::Freeze(note)) => {
+                    u128::from(note.aux_info.fee)
+                }
+                EspressoTransaction::Genesis(_) | EspressoTransaction::Reward(_) => 0,
+            })
+            .sum()
+    }
+
+    /// The length in bytes of the canonical serialization of this block.
+    ///
+    /// This is computed by summing the serialized sizes of the components of the block, without
+    /// actually serializing it. It is not cached, so it takes time linear in the number of
+    /// transactions, and checking it after every
+    /// [add_transaction_raw](ConsensusBlock::add_transaction_raw) takes quadratic time overall.
+    pub fn size_bytes(&self) -> usize {
+        CanonicalSerialize::serialized_size(self)
+    }
+
+    /// The transaction at position `index` in this block, with its helper proofs and memos.
+    ///
+    /// Returns [None] if `index` is out of bounds.
+    pub fn transaction_at(&self, index: usize) -> Option<ElaboratedTransaction> {
+        Some(ElaboratedTransaction {
+            txn: self.block.0.get(index)?.clone(),
+            proofs: self.proofs.get(index)?.clone(),
+            memos: self.memos.get(index)?.clone(),
+            memo: None,
+        })
+    }
+
+    /// The CAP transactions in this block of the given kind, along with their nullifier proofs.
+    ///
+    /// Genesis and reward transactions are never included.
+    pub fn transactions_of_kind(
+        &self,
+        kind: TransactionKind,
+    ) -> impl Iterator<Item = (&TransactionNote, &[SetMerkleProof])> + '_ {
+        self.block
+            .0
+            .iter()
+            .zip(&self.proofs)
+            .filter_map(move |(txn, proofs)| match (txn, proofs) {
+                (EspressoTransaction::CAP(note), EspressoTxnHelperProofs::CAP(proofs))
+                    if TransactionKind::of(note) == kind =>
+                {
+                    Some((note, proofs.as_slice()))
+                }
+                _ => None,
+            })
+    }
+
+    pub fn mint_transactions(
+        &self,
+    ) -> impl Iterator<Item = (&TransactionNote, &[SetMerkleProof])> + '_ {
+        self.transactions_of_kind(TransactionKind::Mint)
+    }
+
+    pub fn transfer_transactions(
+        &self,
+    ) -> impl Iterator<Item = (&TransactionNote, &[SetMerkleProof])> + '_ {
+        self.transactions_of_kind(TransactionKind::Transfer)
+    }
+
+    pub fn freeze_transactions(
+        &self,
+    ) -> impl Iterator<Item = (&TransactionNote, &[SetMerkleProof])> + '_ {
+        self.transactions_of_kind(TransactionKind::Freeze)
+    }
+
+    /// Combine two blocks built on the same parent state into one.
+    ///
+    /// The transactions of `other` are appended after the transactions of `self`. As in
+    /// [add_transaction_raw](ConsensusBlock::add_transaction_raw), this only checks that the two
+    /// blocks do not spend the same nullifier; it does not re-check any proofs.
+    ///
+    /// # Errors
+    /// - [ValidationError::IncorrectParent] if the blocks have different parent states
+    /// - [ValidationError::ConflictingNullifiers]
+    pub fn merge(mut self, other: ElaboratedBlock) -> Result<ElaboratedBlock, ValidationError> {
+        if self.parent_state != other.parent_state {
+            return Err(ValidationError::IncorrectParent);
+        }
+
+        let nulls = self
+            .block
+            .0
+            .iter()
+            .flat_map(|x| x.input_nullifiers().into_iter())
+            .collect::<HashSet<_>>();
+        if other
+            .block
+            .0
+            .iter()
+            .flat_map(|x| x.input_nullifiers().into_iter())
+            .any(|n| nulls.contains(&n))
+        {
+            return Err(ValidationError::ConflictingNullifiers {});
+        }
+
+        self.block.0.extend(other.block.0);
+        self.proofs.extend(other.proofs);
+        self.memos.extend(other.memos);
+        Ok(self)
+    }
+
+    /// Split this block into the transactions which are valid for `state` at time `now` and those
+    /// which are not.
+    ///
+    /// Returns a block, with the same parent, containing only the valid transactions (in their
+    /// original order), along with the index in this block and the error for each invalid
+    /// transaction. Transactions are checked as in
+    /// [validate_block_dry_run](ValidatorState::validate_block_dry_run).
+    pub fn filter_transactions(
+        &self,
+        state: &ValidatorState,
+        now: &ConsensusTime,
+    ) -> (ElaboratedBlock, Vec<(usize, ValidationError)>) {
+        let results = state.validate_block_dry_run(
+            now,
+            self.parent_state,
+            self.block.clone(),
+            self.proofs.clone(),
+        );
+        let mut valid = Self::new(self.parent_state);
+        let mut rejected = vec![];
+        for (i, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(()) => {
+                    valid.block.0.push(self.block.0[i].clone());
+                    valid.proofs.push(self.proofs[i].clone());
+                    valid.memos.push(self.memos.get(i).cloned().flatten());
+                }
+                Err(err) => rejected.push((i, err)),
+            }
+        }
+        (valid, rejected)
+    }
+
+    /// Convert this block to the format in which it is sent over the network.
+    ///
+    /// # Errors
+    /// - [ValidationError::BlockTooLarge]
+    /// - [ValidationError::TooManyProofs]
+    pub fn to_wire_format(&self) -> Result<WireBlock, ValidationError> {
+        let wire = WireBlock {
+            parent_state: self.parent_state,
+            block: self.block.clone(),
+            proofs: self.proofs.clone(),
+            memos: self.memos.clone(),
+        };
+        wire.check_limits()?;
+        Ok(wire)
+    }
+}
+
+/// The form in which an [ElaboratedBlock] is sent over the peer-to-peer network.
+///
+/// A [WireBlock] carries the same data as an [ElaboratedBlock], but it can only be constructed
+/// (via [ElaboratedBlock::to_wire_format]) or consumed (via [WireBlock::to_elaborated]) if it is
+/// within the size limits [MAX_TRANSACTIONS](Self::MAX_TRANSACTIONS) and
+/// [MAX_PROOFS_PER_TRANSACTION](Self::MAX_PROOFS_PER_TRANSACTION). Since a [WireBlock] received
+/// from a peer may have been constructed by anyone, the limits are checked again on the receiving
+/// side.
+#[derive(
+    Debug,
+    Clone,
+    CanonicalSerialize,
+    CanonicalDeserialize,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub struct WireBlock {
+    pub parent_state: LedgerStateCommitment,
+    pub block: Block,
+    pub proofs: Vec<EspressoTxnHelperProofs>,
+    pub memos: Vec<Option<(Vec<ReceiverMemo>, Signature)>>,
+}
+
+impl WireBlock {
+    /// The maximum number of transactions in a block sent over the network.
+    pub const MAX_TRANSACTIONS: usize = 1000;
+    /// The maximum number of nullifier proofs for a single transaction sent over the network.
+    pub const MAX_PROOFS_PER_TRANSACTION: usize = 20;
+
+    fn check_limits(&self) -> Result<(), ValidationError> {
+        if self.block.0.len() > Self::MAX_TRANSACTIONS {
+            return Err(ValidationError::BlockTooLarge {
+                num_txns: self.block.0.len(),
+                max: Self::MAX_TRANSACTIONS,
+            });
+        }
+        for (txn_index, proofs) in self.proofs.iter().enumerate() {
+            if let EspressoTxnHelperProofs::CAP(proofs) = proofs {
+                if proofs.len() > Self::MAX_PROOFS_PER_TRANSACTION {
+                    return Err(ValidationError::TooManyProofs {
+                        txn_index,
+                        num_proofs: proofs.len(),
+                        max: Self::MAX_PROOFS_PER_TRANSACTION,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reconstruct the [ElaboratedBlock] for `state` from its wire format.
+    ///
+    /// The transactions and their helper proofs are checked by [ElaboratedBlock::from_raw_parts].
+    ///
+    /// # Errors
+    /// - [ValidationError::BlockTooLarge]
+    /// - [ValidationError::TooManyProofs]
+    /// - [ValidationError::IncorrectParent] if the block is not intended for `state`
+    /// - [ValidationError::InconsistentHelperProofs] if the number of memos does not match the
+    ///   number of transactions
+    /// - any error from [ElaboratedBlock::from_raw_parts]
+    pub fn to_elaborated(self, state: &ValidatorState) -> Result<ElaboratedBlock, ValidationError> {
+        self.check_limits()?;
+        if self.parent_state != state.commit() {
+            return Err(ValidationError::IncorrectParent);
+        }
+        if self.memos.len() != self.block.0.len() {
+            return Err(ValidationError::InconsistentHelperProofs);
+        }
+        let mut block =
+            ElaboratedBlock::from_raw_parts(self.parent_state, self.block, self.proofs)?;
+        block.memos = self.memos;
+        Ok(block)
+    }
+}
+
+/// Split a block into its transactions.
+///
+/// # Errors
+/// - [ValidationError::InconsistentHelperProofs] if the block does not have exactly one set of
+///   proofs and one set of memos for each transaction
+impl TryFrom<ElaboratedBlock> for Vec<ElaboratedTransaction> {
+    type Error = ValidationError;
+
+    fn try_from(block: ElaboratedBlock) -> Result<Self, ValidationError> {
+        if block.proofs.len() != block.block.0.len() || block.memos.len() != block.block.0.len() {
+            return Err(ValidationError::InconsistentHelperProofs);
+        }
+        Ok(block
+            .block
+            .0
+            .into_iter()
+            .zip(block.proofs)
+            .zip(block.memos)
+            .map(|((txn, proofs), memos)| ElaboratedTransaction {
+                txn,
+                proofs,
+                memos,
+                memo: None,
+            })
+            .collect())
+    }
+}
+
+/// Build a block with the given parent state out of a list of transactions.
+///
+/// # Errors
+/// - [ValidationError::ConflictingNullifiers] if two of the transactions spend the same nullifier
+impl TryFrom<(LedgerStateCommitment, Vec<ElaboratedTransaction>)> for ElaboratedBlock {
+    type Error = ValidationError;
+
+    fn try_from(
+        (parent_state, txns): (LedgerStateCommitment, Vec<ElaboratedTransaction>),
+    ) -> Result<Self, ValidationError> {
+        txns.iter().try_fold(Self::new(parent_state), |block, txn| {
+            block.add_transaction_raw(txn)
+        })
     }
+}
 
-    pub fn len(&self) -> usize {
-        self.block.0.len()
+/// The memos attached to one transaction in an [ElaboratedBlock].
+type TransactionMemos = Option<(Vec<ReceiverMemo>, Signature)>;
+
+/// Iterator over the transactions of an [ElaboratedBlock], taken by value.
+pub type ElaboratedBlockIntoIter = std::iter::Map<
+    std::iter::Zip<
+        std::iter::Zip<
+            std::vec::IntoIter<EspressoTransaction>,
+            std::vec::IntoIter<EspressoTxnHelperProofs>,
+        >,
+        std::vec::IntoIter<TransactionMemos>,
+    >,
+    fn(
+        (
+            (EspressoTransaction, EspressoTxnHelperProofs),
+            TransactionMemos,
+        ),
+    ) -> ElaboratedTransaction,
+>;
+
+/// Iterator over copies of the transactions of an [ElaboratedBlock].
+pub type ElaboratedBlockIter<'a> = std::iter::Map<
+    std::iter::Zip<
+        std::iter::Zip<
+            std::slice::Iter<'a, EspressoTransaction>,
+            std::slice::Iter<'a, EspressoTxnHelperProofs>,
+        >,
+        std::slice::Iter<'a, TransactionMemos>,
+    >,
+    fn(
+        (
+            (&'a EspressoTransaction, &'a EspressoTxnHelperProofs),
+            &'a TransactionMemos,
+        ),
+    ) -> ElaboratedTransaction,
+>;
+
+/// Iterate over the transactions in a block, each paired with its helper proofs and memos.
+///
+/// The transactions are moved out of the block as they are visited, not copied.
+impl IntoIterator for ElaboratedBlock {
+    type Item = ElaboratedTransaction;
+    type IntoIter = ElaboratedBlockIntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.block
+            .0
+            .into_iter()
+            .zip(self.proofs)
+            .zip(self.memos)
+            .map(
+                (|((txn, proofs), memos): (
+                    (EspressoTransaction, EspressoTxnHelperProofs),
+                    TransactionMemos,
+                )| ElaboratedTransaction {
+                    txn,
+                    proofs,
+                    memos,
+                    memo: None,
+                }) as fn(_) -> _,
+            )
     }
+}
 
-    pub fn is_empty(&self) -> bool {
-        self.block.0.is_empty()
+/// Iterate over copies of the transactions in a block, each paired with its helper proofs and
+/// memos.
+///
+/// Each transaction is copied only when it is visited.
+impl<'a> IntoIterator for &'a ElaboratedBlock {
+    type Item = ElaboratedTransaction;
+    type IntoIter = ElaboratedBlockIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.block.0.iter().zip(&self.proofs).zip(&self.memos).map(
+            (|((txn, proofs), memos): (
+                (&EspressoTransaction, &EspressoTxnHelperProofs),
+                &TransactionMemos,
+            )| ElaboratedTransaction {
+                txn: txn.clone(),
+                proofs: proofs.clone(),
+                memos: memos.clone(),
+                memo: None,
+            }) as fn(_) -> _,
+        )
     }
 }
 
@@ -352,6 +1030,16 @@ pub struct ElaboratedBlockCommitment(pub commit::Commitment<ElaboratedBlock>);
 // Implements From<CanonicalBytes>. See serialize.rs in Jellyfish.
 deserialize_canonical_bytes!(ElaboratedBlockCommitment);
 
+impl ElaboratedBlockCommitment {
+    /// Whether `block` is the block committed to by this commitment.
+    ///
+    /// This can be used to check a block body received from an untrusted source against a
+    /// commitment which is already trusted, for example one agreed on by consensus.
+    pub fn verify_block(&self, block: &ElaboratedBlock) -> bool {
+        block.commit() == self.0
+    }
+}
+
 impl Committable for ElaboratedTransaction {
     /// Get a commitment to an elaborated transaction.
     fn commit(&self) -> Commitment<Self> {
@@ -382,7 +1070,7 @@ impl ConsensusBlock for ElaboratedBlock {
             .iter()
             .flat_map(|x| x.input_nullifiers().into_iter())
             .collect::<HashSet<_>>();
-        for n in txn.txn.input_nullifiers().iter() {
+        for n in txn.nullifiers().iter() {
             if nulls.contains(n) {
                 return Err(ValidationError::ConflictingNullifiers {});
             }
@@ -422,24 +1110,33 @@ impl ConsensusBlock for ElaboratedBlock {
 #[snafu(visibility(pub(crate)))]
 pub enum ValidationError {
     /// A record was already spent.
-    NullifierAlreadyExists {
-        nullifier: Nullifier,
-    },
+    #[snafu(display("nullifier {} has already been spent", nullifier))]
+    NullifierAlreadyExists { nullifier: Nullifier },
     /// An invalid nullifier proof.
+    #[snafu(display("invalid nullifier non-membership proof"))]
     BadNullifierProof {},
+    #[snafu(display("missing nullifier proof"))]
     MissingNullifierProof {},
     /// The transaction being added to a block contains a nullifier
     /// already present in another transaction in the block.
+    #[snafu(display(
+        "transaction spends a nullifier which is already spent by another transaction in the block"
+    ))]
     ConflictingNullifiers {},
     /// A generic failure.
+    #[snafu(display("validation failed"))]
     Failed {},
     /// An incorrect Merkle path length.
+    #[snafu(display("Merkle path has incorrect length"))]
     BadMerkleLength {},
     /// An invalid Merkle leaf.
+    #[snafu(display("invalid Merkle leaf"))]
     BadMerkleLeaf {},
     /// An incorrect Merkle root.
+    #[snafu(display("transaction was built against an unknown or expired record Merkle root"))]
     BadMerkleRoot {},
     /// An invalid Merkle path.
+    #[snafu(display("invalid Merkle path"))]
     BadMerklePath {},
     /// An error from the Jellyfish library
     ///
@@ -450,10 +1147,19 @@ pub enum ValidationError {
     /// variant, we will serialize Ok(err) to Err(format(err)), and
     /// when we deserialize we will at least preserve the variant
     /// CryptoError and a String representation of the underlying
-    /// error.
+    /// error. The category of the underlying error is preserved
+    /// separately in `code`, so that it survives serialization.
+    ///
+    /// The wrapped error is reported as the [source](std::error::Error::source)
+    /// of this error and, when the original [TxnApiError] is available, it
+    /// is in turn the source of the wrapper, so it can be recovered with
+    /// `downcast_ref`.
+    #[snafu(display("cryptographic error: {}", err))]
     CryptoError {
-        #[serde(with = "ser_display")]
-        err: Result<TxnApiError, String>,
+        #[snafu(source)]
+        err: CryptoErrorSource,
+        #[serde(default)]
+        code: CryptoErrorCode,
     },
     /// The transfer transaction has an unsupported number of inputs or outputs.
     ///
@@ -462,6 +1168,11 @@ pub enum ValidationError {
     /// transactions. If transactions with a greater number of inputs
     /// or outputs are required, then the universal parameter set for
     /// the ledger must be updated.
+    #[snafu(display(
+        "no transfer verifying key for {} inputs and {} outputs",
+        num_inputs,
+        num_outputs
+    ))]
     UnsupportedTransferSize {
         num_inputs: usize,
         num_outputs: usize,
@@ -473,71 +1184,222 @@ pub enum ValidationError {
     /// transactions. If transactions with a greater number of inputs
     /// or outputs are required, then the universal parameter set for
     /// the ledger must be updated.
-    UnsupportedFreezeSize {
-        num_inputs: usize,
-    },
+    #[snafu(display("no freeze verifying key for {} inputs", num_inputs))]
+    UnsupportedFreezeSize { num_inputs: usize },
 
     /// Block transaction order doesn't match helper proofs
+    #[snafu(display("helper proofs do not match the transactions in the block"))]
     InconsistentHelperProofs,
 
     /// A genesis transaction was included in a non-genesis block
+    #[snafu(display("genesis transaction outside of the genesis block"))]
     UnexpectedGenesis,
 
     /// Attempted to apply a block to a state which was not its intended parent state
+    #[snafu(display("block is not intended for this state"))]
     IncorrectParent,
 
     /// Attempted to apply a block with a time in the past
+    #[snafu(display("block time is earlier than the time of the previous block"))]
     InvalidTime,
 
     /// Bad CollectRewardNote
+    #[snafu(display("invalid reward collection transaction"))]
     BadCollectRewardNote,
 
     /// A record was already spent.
-    RewardAlreadyCollected {
-        reward: CollectedRewards,
-    },
+    #[snafu(display("reward {:?} has already been collected", reward))]
+    RewardAlreadyCollected { reward: CollectedRewards },
 
     /// An invalid Collected Reward proof.
+    #[snafu(display("invalid collected reward proof"))]
     BadCollectedRewardProof {},
 
     /// Stake amount in transaction does not match amount in stake table
+    #[snafu(display("reward amount exceeds the maximum reward"))]
     RewardAmountTooLarge,
 
     /// verification error for stake table proof
+    #[snafu(display("invalid stake table proof"))]
     BadStakeTableProof {},
 
     /// verification error for stake table commitments proof
+    #[snafu(display("invalid stake table commitments proof"))]
     BadStakeTableCommitmentsProof {},
 
     /// Error when calculating block fees
+    #[snafu(display("error calculating block fees"))]
     BadFeeCalculation {},
+
+    /// A transaction has the wrong number of nullifier proofs
+    #[snafu(display(
+        "transaction {}has {} nullifier proofs, but {} nullifiers",
+        txn_index.map(|i| format!("{} ", i)).unwrap_or_default(),
+        got,
+        expected
+    ))]
+    ProofCountMismatch {
+        /// The position of the transaction in its block, if it was checked as part of a block.
+        txn_index: Option<usize>,
+        expected: usize,
+        got: usize,
+    },
+
+    /// A block has more transactions than can be sent over the network
+    #[snafu(display("block has {} transactions, but at most {} are allowed", num_txns, max))]
+    BlockTooLarge { num_txns: usize, max: usize },
+
+    /// A transaction has more nullifier proofs than can be sent over the network
+    #[snafu(display(
+        "transaction {} has {} nullifier proofs, but at most {} are allowed",
+        txn_index,
+        num_proofs,
+        max
+    ))]
+    TooManyProofs {
+        txn_index: usize,
+        num_proofs: usize,
+        max: usize,
+    },
+
+    /// A block spends more nullifiers than the block builder allows
+    #[snafu(display(
+        "block would spend {} nullifiers, but at most {} are allowed",
+        num_nullifiers,
+        max
+    ))]
+    TooManyNullifiers { num_nullifiers: usize, max: usize },
 }
 
-pub(crate) mod ser_display {
-    use serde::de::{Deserialize, Deserializer};
-    use serde::ser::{Serialize, Serializer};
-    use std::fmt::Display;
+/// The category of an error from the Jellyfish library.
+///
+/// Unlike [TxnApiError] itself, this can be serialized, so consumers of a deserialized
+/// [ValidationError::CryptoError] can still tell what kind of error occurred.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CryptoErrorCode {
+    /// A zero-knowledge proof failed to verify.
+    InvalidProof,
+    /// A cryptographic primitive, such as a signature or encryption, failed.
+    PrimitiveFailure,
+    /// A transaction note or one of its components was malformed.
+    MalformedNote,
+    /// Any other error.
+    #[default]
+    Other,
+}
 
-    pub fn serialize<S: Serializer, T: Display>(
-        v: &Result<T, String>,
-        s: S,
-    ) -> Result<S::Ok, S::Error> {
-        let string = match v {
-            Ok(v) => format!("{}", v),
-            Err(string) => string.clone(),
-        };
-        Serialize::serialize(&string, s)
+impl From<&TxnApiError> for CryptoErrorCode {
+    fn from(err: &TxnApiError) -> Self {
+        match err {
+            TxnApiError::FailedSnark { .. } | TxnApiError::FailedTransactionVerification { .. } => {
+                Self::InvalidProof
+            }
+            TxnApiError::FailedPrimitives { .. } => Self::PrimitiveFailure,
+            TxnApiError::FailedSerialization { .. } | TxnApiError::InvalidParameter { .. } => {
+                Self::MalformedNote
+            }
+            _ => Self::Other,
+        }
+    }
+}
+
+/// The underlying error of a [ValidationError::CryptoError].
+///
+/// This is either the original [TxnApiError] or, if the [ValidationError] was deserialized, the
+/// description of the original error.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CryptoErrorSource(#[serde(with = "ser_display")] pub Result<TxnApiError, String>);
+
+impl CryptoErrorSource {
+    /// The description of the underlying error.
+    pub fn message(&self) -> String {
+        match &self.0 {
+            Ok(err) => err.to_string(),
+            Err(msg) => msg.clone(),
+        }
+    }
+}
+
+impl Display for CryptoErrorSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for CryptoErrorSource {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0
+            .as_ref()
+            .ok()
+            .map(|err| err as &(dyn std::error::Error + 'static))
+    }
+}
+
+impl ValidationError {
+    /// Wrap an error from the Jellyfish library, recording its category.
+    pub fn crypto(err: TxnApiError) -> Self {
+        Self::CryptoError {
+            code: CryptoErrorCode::from(&err),
+            err: CryptoErrorSource(Ok(err)),
+        }
+    }
+
+    /// Whether this error means the transaction can never be valid, so it should be dropped.
+    ///
+    /// Permanent errors are intrinsic to the transaction (a bad proof, an unsupported size) or to
+    /// the ledger (a nullifier which is already spent, a reward which is already collected), and
+    /// retrying will not help. All other errors are [transient](Self::is_transient).
+    pub fn is_permanent(&self) -> bool {
+        use ValidationError::*;
+        match self {
+            NullifierAlreadyExists { .. }
+            | BadMerkleLength {}
+            | BadMerkleLeaf {}
+            | BadMerklePath {}
+            | CryptoError { .. }
+            | UnsupportedTransferSize { .. }
+            | UnsupportedFreezeSize { .. }
+            | UnexpectedGenesis
+            | BadCollectRewardNote
+            | RewardAlreadyCollected { .. }
+            | BadCollectedRewardProof {}
+            | RewardAmountTooLarge
+            | BadStakeTableProof {}
+            | BadStakeTableCommitmentsProof {}
+            | BadFeeCalculation {}
+            | ProofCountMismatch { .. }
+            | TooManyProofs { .. }
+            | Failed {} => true,
+
+            // The transaction was built or proven against a state which is out of date (or not yet
+            // known), and may succeed once the chain or its proofs catch up.
+            BadMerkleRoot {}
+            | BadNullifierProof {}
+            | MissingNullifierProof {}
+            // The problem is with the block the transaction was added to, not the transaction
+            // itself, so it may succeed in a different block.
+            | ConflictingNullifiers {}
+            | InconsistentHelperProofs
+            | IncorrectParent
+            | InvalidTime
+            | BlockTooLarge { .. }
+            | TooManyNullifiers { .. } => false,
+        }
     }
 
-    pub fn deserialize<'de, D: Deserializer<'de>, T>(d: D) -> Result<Result<T, String>, D::Error> {
-        Ok(Err(Deserialize::deserialize(d)?))
+    /// Whether this error may go away if the transaction is retried later.
+    ///
+    /// This is the opposite of [is_permanent](Self::is_permanent).
+    pub fn is_transient(&self) -> bool {
+        !self.is_permanent()
     }
 }
 
 /// Adapter because [TxnApiError] doesn't implement Clone
 impl Clone for ValidationError {
-    /// Clone all errors except CryptoError which gets mapped to a
-    /// generic Failed error
+    /// Clone all errors. The [TxnApiError] inside a CryptoError is replaced by its description,
+    /// as if the error had been serialized and deserialized.
     fn clone(&self) -> Self {
         use ValidationError::*;
         match self {
@@ -552,7 +1414,10 @@ impl Clone for ValidationError {
             BadMerkleLeaf {} => BadMerkleLeaf {},
             BadMerkleRoot {} => BadMerkleRoot {},
             BadMerklePath {} => BadMerklePath {},
-            CryptoError { .. } => Failed {},
+            CryptoError { err, code } => CryptoError {
+                err: CryptoErrorSource(Err(err.message())),
+                code: *code,
+            },
             UnsupportedTransferSize {
                 num_inputs,
                 num_outputs,
@@ -576,10 +1441,243 @@ impl Clone for ValidationError {
             BadStakeTableProof {} => BadStakeTableProof {},
             BadStakeTableCommitmentsProof {} => BadStakeTableCommitmentsProof {},
             BadFeeCalculation {} => BadFeeCalculation {},
+            ProofCountMismatch {
+                txn_index,
+                expected,
+                got,
+            } => ProofCountMismatch {
+                txn_index: *txn_index,
+                expected: *expected,
+                got: *got,
+            },
+            BlockTooLarge { num_txns, max } => BlockTooLarge {
+                num_txns: *num_txns,
+                max: *max,
+            },
+            TooManyProofs {
+                txn_index,
+                num_proofs,
+                max,
+            } => TooManyProofs {
+                txn_index: *txn_index,
+                num_proofs: *num_proofs,
+                max: *max,
+            },
+            TooManyNullifiers {
+                num_nullifiers,
+                max,
+            } => TooManyNullifiers {
+                num_nullifiers: *num_nullifiers,
+                max: *max,
+            },
+        }
+    }
+}
+
+/// A [ValidationError] which can always be cloned and serialized without losing information.
+///
+/// This mirrors [ValidationError] variant for variant, except that
+/// [CryptoError](PublicValidationError::CryptoError) carries only a description of the underlying
+/// [TxnApiError]. API layers can convert a [ValidationError] into this type before sending it over
+/// the wire.
+#[derive(Clone, Debug, PartialEq, Eq, Snafu, Serialize, Deserialize)]
+#[snafu(module)]
+pub enum PublicValidationError {
+    /// A record was already spent.
+    #[snafu(display("nullifier {} has already been spent", nullifier))]
+    NullifierAlreadyExists { nullifier: Nullifier },
+    /// An invalid nullifier proof.
+    #[snafu(display("invalid nullifier non-membership proof"))]
+    BadNullifierProof {},
+    #[snafu(display("missing nullifier proof"))]
+    MissingNullifierProof {},
+    /// The transaction being added to a block contains a nullifier
+    /// already present in another transaction in the block.
+    #[snafu(display(
+        "transaction spends a nullifier which is already spent by another transaction in the block"
+    ))]
+    ConflictingNullifiers {},
+    /// A generic failure.
+    #[snafu(display("validation failed"))]
+    Failed {},
+    /// An incorrect Merkle path length.
+    #[snafu(display("Merkle path has incorrect length"))]
+    BadMerkleLength {},
+    /// An invalid Merkle leaf.
+    #[snafu(display("invalid Merkle leaf"))]
+    BadMerkleLeaf {},
+    /// An incorrect Merkle root.
+    #[snafu(display("transaction was built against an unknown or expired record Merkle root"))]
+    BadMerkleRoot {},
+    /// An invalid Merkle path.
+    #[snafu(display("invalid Merkle path"))]
+    BadMerklePath {},
+    /// An error from the Jellyfish library, with the original error rendered as a string.
+    #[snafu(display("cryptographic error: {}", err))]
+    CryptoError { err: String, code: CryptoErrorCode },
+    /// The transfer transaction has an unsupported number of inputs or outputs.
+    #[snafu(display(
+        "no transfer verifying key for {} inputs and {} outputs",
+        num_inputs,
+        num_outputs
+    ))]
+    UnsupportedTransferSize {
+        num_inputs: usize,
+        num_outputs: usize,
+    },
+    /// The freeze transaction has an unsupported number of inputs or outputs.
+    #[snafu(display("no freeze verifying key for {} inputs", num_inputs))]
+    UnsupportedFreezeSize { num_inputs: usize },
+    /// Block transaction order doesn't match helper proofs
+    #[snafu(display("helper proofs do not match the transactions in the block"))]
+    InconsistentHelperProofs,
+    /// A genesis transaction was included in a non-genesis block
+    #[snafu(display("genesis transaction outside of the genesis block"))]
+    UnexpectedGenesis,
+    /// Attempted to apply a block to a state which was not its intended parent state
+    #[snafu(display("block is not intended for this state"))]
+    IncorrectParent,
+    /// Attempted to apply a block with a time in the past
+    #[snafu(display("block time is earlier than the time of the previous block"))]
+    InvalidTime,
+    /// Bad CollectRewardNote
+    #[snafu(display("invalid reward collection transaction"))]
+    BadCollectRewardNote,
+    /// A record was already spent.
+    #[snafu(display("reward {:?} has already been collected", reward))]
+    RewardAlreadyCollected { reward: CollectedRewards },
+    /// An invalid Collected Reward proof.
+    #[snafu(display("invalid collected reward proof"))]
+    BadCollectedRewardProof {},
+    /// Stake amount in transaction does not match amount in stake table
+    #[snafu(display("reward amount exceeds the maximum reward"))]
+    RewardAmountTooLarge,
+    /// verification error for stake table proof
+    #[snafu(display("invalid stake table proof"))]
+    BadStakeTableProof {},
+    /// verification error for stake table commitments proof
+    #[snafu(display("invalid stake table commitments proof"))]
+    BadStakeTableCommitmentsProof {},
+    /// Error when calculating block fees
+    #[snafu(display("error calculating block fees"))]
+    BadFeeCalculation {},
+    /// A transaction has the wrong number of nullifier proofs
+    #[snafu(display(
+        "transaction {}has {} nullifier proofs, but {} nullifiers",
+        txn_index.map(|i| format!("{} ", i)).unwrap_or_default(),
+        got,
+        expected
+    ))]
+    ProofCountMismatch {
+        /// The position of the transaction in its block, if it was checked as part of a block.
+        txn_index: Option<usize>,
+        expected: usize,
+        got: usize,
+    },
+    /// A block has more transactions than can be sent over the network
+    #[snafu(display("block has {} transactions, but at most {} are allowed", num_txns, max))]
+    BlockTooLarge { num_txns: usize, max: usize },
+    /// A transaction has more nullifier proofs than can be sent over the network
+    #[snafu(display(
+        "transaction {} has {} nullifier proofs, but at most {} are allowed",
+        txn_index,
+        num_proofs,
+        max
+    ))]
+    TooManyProofs {
+        txn_index: usize,
+        num_proofs: usize,
+        max: usize,
+    },
+    /// A block spends more nullifiers than the block builder allows
+    #[snafu(display(
+        "block would spend {} nullifiers, but at most {} are allowed",
+        num_nullifiers,
+        max
+    ))]
+    TooManyNullifiers { num_nullifiers: usize, max: usize },
+}
+
+impl From<ValidationError> for PublicValidationError {
+    fn from(err: ValidationError) -> Self {
+        use ValidationError::*;
+        match err {
+            NullifierAlreadyExists { nullifier } => Self::NullifierAlreadyExists { nullifier },
+            BadNullifierProof {} => Self::BadNullifierProof {},
+            MissingNullifierProof {} => Self::MissingNullifierProof {},
+            ConflictingNullifiers {} => Self::ConflictingNullifiers {},
+            Failed {} => Self::Failed {},
+            BadMerkleLength {} => Self::BadMerkleLength {},
+            BadMerkleLeaf {} => Self::BadMerkleLeaf {},
+            BadMerkleRoot {} => Self::BadMerkleRoot {},
+            BadMerklePath {} => Self::BadMerklePath {},
+            CryptoError { err, code } => Self::CryptoError {
+                err: err.message(),
+                code,
+            },
+            UnsupportedTransferSize {
+                num_inputs,
+                num_outputs,
+            } => Self::UnsupportedTransferSize {
+                num_inputs,
+                num_outputs,
+            },
+            UnsupportedFreezeSize { num_inputs } => Self::UnsupportedFreezeSize { num_inputs },
+            InconsistentHelperProofs => Self::InconsistentHelperProofs,
+            UnexpectedGenesis => Self::UnexpectedGenesis,
+            IncorrectParent => Self::IncorrectParent,
+            InvalidTime => Self::InvalidTime,
+            BadCollectRewardNote => Self::BadCollectRewardNote,
+            RewardAlreadyCollected { reward } => Self::RewardAlreadyCollected { reward },
+            BadCollectedRewardProof {} => Self::BadCollectedRewardProof {},
+            RewardAmountTooLarge => Self::RewardAmountTooLarge,
+            BadStakeTableProof {} => Self::BadStakeTableProof {},
+            BadStakeTableCommitmentsProof {} => Self::BadStakeTableCommitmentsProof {},
+            BadFeeCalculation {} => Self::BadFeeCalculation {},
+            ProofCountMismatch {
+                txn_index,
+                expected,
+                got,
+            } => Self::ProofCountMismatch {
+                txn_index,
+                expected,
+                got,
+            },
+            BlockTooLarge { num_txns, max } => Self::BlockTooLarge { num_txns, max },
+            TooManyProofs {
+                txn_index,
+                num_proofs,
+                max,
+            } => Self::TooManyProofs {
+                txn_index,
+                num_proofs,
+                max,
+            },
+            TooManyNullifiers {
+                num_nullifiers,
+                max,
+            } => Self::TooManyNullifiers {
+                num_nullifiers,
+                max,
+            },
         }
     }
 }
 
+/// Errors from [ValidatorState::rewind].
+#[derive(Clone, Debug, Snafu, Serialize, Deserialize)]
+pub enum RewindError {
+    /// The target state is not reached by applying the given blocks to the starting state.
+    #[snafu(display("target state {} is not reachable from the given history", target))]
+    Unreachable { target: LedgerStateCommitment },
+    /// One of the blocks in the history could not be applied.
+    #[snafu(display("failed to apply block {} of the history: {}", index, source))]
+    InvalidBlock {
+        index: usize,
+        source: ValidationError,
+    },
+}
+
 impl Committable for Block {
     fn commit(&self) -> commit::Commitment<Self> {
         commit::RawCommitmentBuilder::new("Block Comm")
@@ -599,9 +1697,45 @@ impl Committable for Block {
 )]
 pub struct TransactionCommitment(pub commit::Commitment<EspressoTransaction>);
 
+/// Transaction commitments are ordered by their bytes, so they can be used as [BTreeMap] keys.
+///
+/// [BTreeMap]: std::collections::BTreeMap
+impl Ord for TransactionCommitment {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.as_ref().cmp(other.0.as_ref())
+    }
+}
+
+impl PartialOrd for TransactionCommitment {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 // Implements From<CanonicalBytes>. See serialize.rs in Jellyfish.
 deserialize_canonical_bytes!(TransactionCommitment);
 
+/// Formats the bytes of the commitment as 64 hex digits, with a `0x` prefix if the alternate flag
+/// (`{:#x}`) is given.
+impl fmt::LowerHex for TransactionCommitment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "0x")?;
+        }
+        write!(f, "{}", hex::encode(self.0.as_ref()))
+    }
+}
+
+/// Like [LowerHex](fmt::LowerHex), but with upper case hex digits.
+impl fmt::UpperHex for TransactionCommitment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "0x")?;
+        }
+        write!(f, "{}", hex::encode_upper(self.0.as_ref()))
+    }
+}
+
 /// Sliding window for transaction freshness
 ///
 /// We keep a fixed number of recent Merkle root hashes here to allow
@@ -618,15 +1752,112 @@ deserialize_canonical_bytes!(TransactionCommitment);
 /// remembering a fixed number of recent Merkle roots, validators can
 /// validate slightly old transactions while maintaining constant
 /// space requirements for validation.
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct RecordMerkleHistory(pub VecDeque<NodeValue>);
+///
+/// Since checking whether a transaction's root is in the history is on the hot path of block
+/// validation, the history also keeps an index of the roots it contains, so that
+/// [contains](Self::contains) takes constant time regardless of the size of the history.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(from = "VecDeque<NodeValue>", into = "VecDeque<NodeValue>")]
+pub struct RecordMerkleHistory {
+    roots: VecDeque<NodeValue>,
+    /// The number of times each root appears in `roots`.
+    ///
+    /// The same root can appear more than once, for example if a block creates no new records.
+    counts: HashMap<NodeValue, usize>,
+}
+
+impl RecordMerkleHistory {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            roots: VecDeque::with_capacity(capacity),
+            counts: HashMap::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    /// The roots in the history, from most to least recent.
+    pub fn iter(&self) -> impl Iterator<Item = &NodeValue> {
+        self.roots.iter()
+    }
+
+    /// Whether `root` is one of the roots in the history.
+    pub fn contains(&self, root: &NodeValue) -> bool {
+        self.counts.contains_key(root)
+    }
+
+    /// Add a new most recent root.
+    pub fn push_front(&mut self, root: NodeValue) {
+        self.roots.push_front(root);
+        *self.counts.entry(root).or_default() += 1;
+    }
+
+    /// Add a new most recent root, removing the least recent roots until at most `max_size` remain.
+    ///
+    /// Room is made before the new root is added, so once the history is full, each push updates
+    /// the index with exactly one removal and one insertion.
+    pub fn push_front_capped(&mut self, root: NodeValue, max_size: usize) {
+        if max_size == 0 {
+            self.clear();
+            return;
+        }
+        self.truncate_to(max_size - 1);
+        self.push_front(root);
+    }
+
+    /// Remove the least recent root.
+    pub fn pop_back(&mut self) -> Option<NodeValue> {
+        let root = self.roots.pop_back()?;
+        if let Entry::Occupied(mut count) = self.counts.entry(root) {
+            *count.get_mut() -= 1;
+            if *count.get() == 0 {
+                count.remove();
+            }
+        }
+        Some(root)
+    }
+
+    /// Remove the least recent roots until at most `max_len` remain.
+    pub fn truncate_to(&mut self, max_len: usize) {
+        while self.len() > max_len {
+            self.pop_back();
+        }
+    }
+
+    /// Remove all roots from the history.
+    pub fn clear(&mut self) {
+        self.truncate_to(0);
+    }
+}
+
+impl From<VecDeque<NodeValue>> for RecordMerkleHistory {
+    fn from(roots: VecDeque<NodeValue>) -> Self {
+        let mut counts = HashMap::<NodeValue, usize>::new();
+        for root in &roots {
+            *counts.entry(*root).or_default() += 1;
+        }
+        Self { roots, counts }
+    }
+}
+
+impl From<RecordMerkleHistory> for VecDeque<NodeValue> {
+    fn from(history: RecordMerkleHistory) -> Self {
+        history.roots
+    }
+}
 
 impl Committable for RecordMerkleHistory {
     fn commit(&self) -> commit::Commitment<Self> {
         let mut ret = commit::RawCommitmentBuilder::new("Hist Comm")
             .constant_str("roots")
-            .u64(self.0.len() as u64);
-        for n in self.0.iter() {
+            .u64(self.roots.len() as u64);
+        for n in self.roots.iter() {
             ret = ret.var_size_bytes(&canonical::serialize(n).unwrap())
         }
         ret.finalize()
@@ -637,6 +1868,17 @@ impl Committable for RecordMerkleHistory {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RecordMerkleCommitment(pub MerkleCommitment);
 
+impl RecordMerkleCommitment {
+    /// Whether `frontier` is the frontier of a record Merkle tree with commitment `commitment`.
+    ///
+    /// This performs the same check as [MerkleTree::restore_from_frontier], which returns [None]
+    /// without saying why if the commitment and frontier are inconsistent. Checking first lets
+    /// callers distinguish a mismatched frontier from other failures.
+    pub fn matches_frontier(commitment: &MerkleCommitment, frontier: &MerkleFrontier) -> bool {
+        MerkleTree::restore_from_frontier(*commitment, frontier).is_some()
+    }
+}
+
 impl Committable for RecordMerkleCommitment {
     fn commit(&self) -> commit::Commitment<Self> {
         commit::RawCommitmentBuilder::new("RMT Comm")
@@ -733,6 +1975,30 @@ impl NullifierHistory {
         self.count
     }
 
+    /// The nullifiers appended by the last `num_blocks` blocks.
+    ///
+    /// Returns the nullifiers in the order they were appended, together with the root hash of the
+    /// set before the first of them was appended. Returns [None] if `num_blocks` is greater than
+    /// the number of blocks in the history.
+    pub fn last_blocks(&self, num_blocks: usize) -> Option<(set_hash::Hash, Vec<Nullifier>)> {
+        if num_blocks > self.history.len() {
+            return None;
+        }
+        let root = match num_blocks.checked_sub(1) {
+            Some(i) => self.history[i].0.hash(),
+            None => self.current,
+        };
+        let nulls = self
+            .history
+            .iter()
+            .take(num_blocks)
+            .rev()
+            .flat_map(|(_, nulls)| nulls)
+            .cloned()
+            .collect();
+        Some((root, nulls))
+    }
+
     /// Check if a nullifier has been spent.
     ///
     /// This function succeeds if `proof` is valid relative to some recent nullifier set (less than
@@ -941,6 +2207,64 @@ impl Committable for NullifierHistory {
     }
 }
 
+/// The changes to a [ValidatorState] made by a sequence of blocks.
+///
+/// This is produced by [ValidatorState::state_diff]. It contains everything a light client which
+/// tracks the nullifiers it cares about and the record Merkle frontier needs to move from the older
+/// state to the newer one. The new record commitments themselves are not included: only the
+/// frontier of the record Merkle tree is part of the state. They are the outputs of the blocks in
+/// between, starting at UID `first_record_uid`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StateDiff {
+    /// Nullifiers spent since the older state, in the order they were spent.
+    pub new_nullifiers: Vec<Nullifier>,
+    /// The UID of the first record created since the older state.
+    pub first_record_uid: u64,
+    /// The new record Merkle root and number of records.
+    pub record_merkle_commitment: MerkleCommitment,
+    /// The new record Merkle frontier.
+    pub record_merkle_frontier: MerkleFrontier,
+    /// The commitment to the newer state.
+    pub commitment: LedgerStateCommitment,
+}
+
+/// Nullifiers spent by transactions which are queued for inclusion in a block.
+///
+/// A [ValidatorState] only knows about nullifiers which have been spent by committed blocks. Block
+/// builders can use a [MempoolState] alongside it to track nullifiers which are "virtually spent"
+/// by transactions they have already accepted but not yet sealed in a block, so that conflicting
+/// transactions can be rejected early. It is purely local bookkeeping and is not part of the ledger
+/// state or its commitment.
+#[derive(Clone, Debug, Default)]
+pub struct MempoolState {
+    pending_nullifiers: HashSet<Nullifier>,
+}
+
+impl MempoolState {
+    /// Record that `nullifier` is spent by a queued transaction.
+    pub fn add_pending(&mut self, nullifier: Nullifier) {
+        self.pending_nullifiers.insert(nullifier);
+    }
+
+    /// Forget a pending nullifier, for example because its transaction was included in a block or
+    /// dropped from the queue.
+    pub fn remove_pending(&mut self, nullifier: &Nullifier) {
+        self.pending_nullifiers.remove(nullifier);
+    }
+
+    /// Whether `nullifier` is spent by a queued transaction.
+    ///
+    /// This does not check whether `nullifier` has been spent by a committed block.
+    pub fn is_virtually_spent(&self, nullifier: &Nullifier) -> bool {
+        self.pending_nullifiers.contains(nullifier)
+    }
+
+    /// All nullifiers spent by queued transactions.
+    pub fn pending_nullifiers(&self) -> &HashSet<Nullifier> {
+        &self.pending_nullifiers
+    }
+}
+
 /// The ledger state commitment
 ///
 /// Fundamental to a distributed ledger is the notion of a state
@@ -984,6 +2308,25 @@ pub mod state_comm {
         }
     }
 
+    impl LedgerStateCommitment {
+        /// Hex encoding of the canonical bytes of this commitment.
+        ///
+        /// This encodes the same bytes as the `STATE~` tagged base64 representation, for use with
+        /// external systems which expect hex.
+        pub fn to_hex(&self) -> String {
+            hex::encode(canonical::serialize(self).unwrap())
+        }
+
+        /// Parse a commitment from the encoding produced by [LedgerStateCommitment::to_hex].
+        ///
+        /// An optional `0x` prefix is accepted.
+        pub fn from_hex(s: &str) -> Result<Self, SerializationError> {
+            let bytes = hex::decode(s.strip_prefix("0x").unwrap_or(s))
+                .map_err(|_| SerializationError::InvalidData)?;
+            canonical::deserialize(&bytes)
+        }
+    }
+
     /// Wrapper around amount to make it committable
     #[derive(
         Debug,
@@ -1009,12 +2352,52 @@ pub mod state_comm {
                 .finalize()
         }
     }
+
+    /// Wrapper around [VerifierKeySet] to make it committable
+    #[derive(Clone, Debug, From, Into)]
+    pub struct CommittableVerifierKeySet(pub ArcSer<VerifierKeySet>);
+
+    impl Committable for CommittableVerifierKeySet {
+        fn commit(&self) -> Commitment<Self> {
+            commit::RawCommitmentBuilder::new("VerifierKeySet")
+                .var_size_bytes(&canonical::serialize(&self.0).unwrap())
+                .finalize()
+        }
+    }
+
+    impl CommittableVerifierKeySet {
+        /// Commitment to the verifier keys.
+        ///
+        /// This is the same as [Committable::commit], but it is not easily confused with the many
+        /// other `commit` methods, such as [ValidatorState::commit].
+        pub fn to_commitment(&self) -> Commitment<Self> {
+            self.commit()
+        }
+    }
+
+    /// Verifier key sets are compared by their canonical serializations.
+    impl PartialEq for CommittableVerifierKeySet {
+        fn eq(&self, other: &Self) -> bool {
+            self.commit() == other.commit()
+        }
+    }
+
+    impl Eq for CommittableVerifierKeySet {}
+
+    impl Hash for CommittableVerifierKeySet {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            Hash::hash(&self.commit(), state)
+        }
+    }
+
     /// The essential state of the ledger
     ///
     /// Note that many elements of the state are represented
     /// succinctly as cryptographic commitments.
-    #[derive(Debug)]
+    #[derive(Clone, Debug, Serialize, Deserialize)]
     pub struct LedgerCommitmentOpening {
+        /// The chain variables include the size of the record Merkle root history, so validators
+        /// configured with different history sizes cannot agree on a state.
         pub chain: Commitment<ChainVariables>,
         pub prev_commit_time: ConsensusTime,
         pub block_height: u64,
@@ -1054,7 +2437,73 @@ pub mod state_comm {
         pub collected_rewards: Commitment<CollectedRewardsHistory>,
     }
 
-    impl LedgerCommitmentOpening {
+    impl LedgerCommitmentOpening {
+        /// The opening of the commitment to `state`.
+        pub fn from_validator_state(state: &ValidatorState) -> Self {
+            Self {
+                chain: state.chain_commitment(),
+                prev_commit_time: state.prev_commit_time,
+                block_height: state.block_height,
+                transaction_count: state.transaction_count,
+                prev_state: state.prev_state,
+                record_merkle_commitment: RecordMerkleCommitment(state.record_merkle_commitment)
+                    .commit(),
+                record_merkle_frontier: RecordMerkleFrontier(state.record_merkle_frontier.clone())
+                    .commit(),
+                past_record_merkle_roots: state.past_record_merkle_roots.commit(),
+                past_nullifiers: state.past_nullifiers.commit(),
+                prev_block: state.prev_block,
+                stake_table_root: state.stake_table_root.commit(),
+                total_stake: CommittableAmount::from(state.total_stake).commit(),
+                historical_stake_tables: CommittableStakeTableSetFrontier(
+                    state.historical_stake_tables.clone(),
+                )
+                .commit(),
+                past_stc_merkle_roots: state.past_historial_stake_table_merkle_roots.commit(),
+                historial_stake_tables_commitment: CommittableStakeTableSetCommitment(
+                    state.historical_stake_tables_commitment,
+                )
+                .commit(),
+                collected_rewards: state.collected_rewards.commit(),
+            }
+        }
+
+        /// The opening of the commitment to the empty state of a chain which uses `verif_crs`.
+        ///
+        /// This is the same as the opening of
+        /// [ValidatorState::from_verifier_keys]`(verif_crs)`, but it is computed from empty trees
+        /// without materializing the state itself.
+        pub fn empty(verif_crs: &VerifierKeySet) -> Self {
+            let record_merkle_tree = MerkleTree::new(MERKLE_HEIGHT).unwrap();
+            let stake_table_commitments_mt = StakeTableSetMT::new(MERKLE_HEIGHT).unwrap();
+            Self {
+                chain: ChainVariables::new(0, Arc::new(verif_crs.clone()), 0).commit(),
+                prev_commit_time: ConsensusTime::genesis(),
+                block_height: 0,
+                transaction_count: 0,
+                prev_state: None,
+                record_merkle_commitment: RecordMerkleCommitment(record_merkle_tree.commitment())
+                    .commit(),
+                record_merkle_frontier: RecordMerkleFrontier(record_merkle_tree.frontier())
+                    .commit(),
+                past_record_merkle_roots: RecordMerkleHistory::default().commit(),
+                past_nullifiers: NullifierHistory::default().commit(),
+                prev_block: Block::empty_commitment(),
+                stake_table_root: StakeTableCommitment(StakeTableMap::EmptySubtree.hash()).commit(),
+                total_stake: CommittableAmount::from(Amount::from(0u64)).commit(),
+                historical_stake_tables: CommittableStakeTableSetFrontier(
+                    stake_table_commitments_mt.frontier(),
+                )
+                .commit(),
+                past_stc_merkle_roots: StakeTableSetHistory(VecDeque::new()).commit(),
+                historial_stake_tables_commitment: CommittableStakeTableSetCommitment(
+                    stake_table_commitments_mt.commitment(),
+                )
+                .commit(),
+                collected_rewards: CollectedRewardsHistory::default().commit(),
+            }
+        }
+
         pub fn commit(&self) -> LedgerStateCommitment {
             commit::RawCommitmentBuilder::new("Ledger Comm")
                 .field("chain", self.chain)
@@ -1166,6 +2615,19 @@ pub struct ChainVariables {
 
     /// Committee size
     pub committee_size: u64,
+
+    /// The number of recent record Merkle roots validators remember.
+    ///
+    /// This affects which transactions are valid, so every node of a chain must use the same
+    /// value. It is set at genesis, and defaults to [ValidatorState::HISTORY_SIZE].
+    ///
+    /// **Breaking change**: this field is part of the canonical (binary) serialization of
+    /// [ChainVariables], and so of [GenesisNote](crate::genesis::GenesisNote), where it follows
+    /// `committee_size` as a little-endian `u64`. Binary encodings made before it was added cannot
+    /// be deserialized. (The serde encoding is unaffected, since a missing field gets the
+    /// default.)
+    #[serde(default = "ChainVariables::default_record_root_history_size")]
+    pub record_root_history_size: usize,
 }
 
 #[tagged_blob("VRFSEED")]
@@ -1220,6 +2682,10 @@ impl Committable for ChainVariables {
             .var_size_bytes(&canonical::serialize(&self.verif_crs).unwrap())
             .fixed_size_bytes(self.vrf_seed.as_ref())
             .u64_field("committee size", self.committee_size)
+            .u64_field(
+                "record_root_history_size",
+                self.record_root_history_size as u64,
+            )
             .finalize()
     }
 }
@@ -1232,6 +2698,7 @@ impl<'a> Arbitrary<'a> for ChainVariables {
             verif_crs: VERIF_CRS.clone().into(),
             vrf_seed: u.arbitrary()?,
             committee_size: u.arbitrary()?,
+            record_root_history_size: u.int_in_range(1..=2 * ValidatorState::HISTORY_SIZE)?,
         })
     }
 }
@@ -1266,8 +2733,19 @@ impl ChainVariables {
                 .finalize()
                 .into(),
             committee_size,
+            record_root_history_size: ValidatorState::HISTORY_SIZE,
         }
     }
+
+    /// Use a record Merkle root history of `size` instead of the default.
+    pub fn with_record_root_history_size(mut self, size: usize) -> Self {
+        self.record_root_history_size = size;
+        self
+    }
+
+    fn default_record_root_history_size() -> usize {
+        ValidatorState::HISTORY_SIZE
+    }
 }
 
 /// The working state of the ledger
@@ -1277,7 +2755,20 @@ impl ChainVariables {
 #[ser_test(arbitrary, ark(false))]
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ValidatorState {
-    pub chain: ChainVariables,
+    /// The chain variables, which can only be replaced through [set_chain](Self::set_chain), so
+    /// that the cached commitments below stay up to date.
+    chain: ChainVariables,
+    /// Cached commitment to `chain`.
+    ///
+    /// Committing to `chain` requires hashing all of the verifier key material, so we do it at
+    /// most once for each value of `chain`, rather than every time the state is committed. The
+    /// cache is not serialized; it is recomputed on demand after deserialization.
+    #[serde(skip)]
+    chain_commitment: OnceCell<Commitment<ChainVariables>>,
+    /// Cached commitment to the verifier keys in `chain`, computed on demand like
+    /// `chain_commitment`.
+    #[serde(skip)]
+    verif_crs_commitment: OnceCell<Commitment<CommittableVerifierKeySet>>,
     /// The consensus time at which this state was created.
     ///
     /// "Consensus time" is an opaque notion of time which is meaningful in the consensus layer.
@@ -1317,6 +2808,17 @@ pub struct ValidatorState {
     pub collected_rewards: CollectedRewardsHistory,
 }
 
+/// The spend status of a nullifier, according to a [ValidatorState].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NullifierStatus {
+    /// The nullifier has been spent.
+    Spent,
+    /// The nullifier has not been spent.
+    Unspent,
+    /// The proof is not valid relative to any recent nullifier set.
+    InvalidProof,
+}
+
 /// Nullifier proofs, organized by the root hash for which they are valid.
 pub type NullifierProofs = Vec<(Nullifier, SetMerkleProof, set_hash::Hash)>;
 
@@ -1325,45 +2827,13 @@ pub type VerifiedRewards = Vec<CollectedRewardsProof>;
 
 impl Default for ValidatorState {
     fn default() -> Self {
-        Self::new(
-            ChainVariables::default(),
-            MerkleTree::new(MERKLE_HEIGHT).unwrap(),
-            StakeTableCommitment(StakeTableMap::EmptySubtree.hash()),
-            Amount::from(0u64),
-            StakeTableSetMT::new(MERKLE_HEIGHT).unwrap(),
-        )
+        Self::empty(ChainVariables::default())
     }
 }
 
 impl Committable for ValidatorState {
     fn commit(&self) -> Commitment<Self> {
-        let inputs = state_comm::LedgerCommitmentOpening {
-            chain: self.chain.commit(),
-            prev_commit_time: self.prev_commit_time,
-            block_height: self.block_height,
-            transaction_count: self.transaction_count,
-            prev_state: self.prev_state,
-            record_merkle_commitment: RecordMerkleCommitment(self.record_merkle_commitment)
-                .commit(),
-            record_merkle_frontier: RecordMerkleFrontier(self.record_merkle_frontier.clone())
-                .commit(),
-            past_record_merkle_roots: self.past_record_merkle_roots.commit(),
-            past_nullifiers: self.past_nullifiers.commit(),
-            prev_block: self.prev_block,
-            stake_table_root: self.stake_table_root.commit(),
-            total_stake: CommittableAmount::from(self.total_stake).commit(),
-            historical_stake_tables: CommittableStakeTableSetFrontier(
-                self.historical_stake_tables.clone(),
-            )
-            .commit(),
-            past_stc_merkle_roots: self.past_historial_stake_table_merkle_roots.commit(),
-            historial_stake_tables_commitment: CommittableStakeTableSetCommitment(
-                self.historical_stake_tables_commitment,
-            )
-            .commit(),
-            collected_rewards: self.collected_rewards.commit(),
-        };
-        inputs.commit().into()
+        self.commitment_opening().commit().into()
     }
 }
 
@@ -1382,19 +2852,20 @@ impl ValidatorState {
         total_stake: Amount,
         stake_table_commitments_mt: StakeTableSetMT,
     ) -> Self {
+        let history_size = chain.record_root_history_size;
         Self {
             chain,
+            chain_commitment: OnceCell::new(),
+            verif_crs_commitment: OnceCell::new(),
             prev_commit_time: ConsensusTime::genesis(),
             block_height: 0u64,
             transaction_count: 0,
             prev_state: None,
             record_merkle_commitment: record_merkle_frontier.commitment(),
             record_merkle_frontier: record_merkle_frontier.frontier(),
-            past_record_merkle_roots: RecordMerkleHistory(VecDeque::with_capacity(
-                Self::HISTORY_SIZE,
-            )),
+            past_record_merkle_roots: RecordMerkleHistory::with_capacity(history_size),
             past_nullifiers: NullifierHistory::default(),
-            prev_block: Block::default().commit(),
+            prev_block: Block::empty_commitment(),
             stake_table_root: stake_table_map_root,
             total_stake,
             historical_stake_tables: stake_table_commitments_mt.frontier(),
@@ -1406,17 +2877,243 @@ impl ValidatorState {
         }
     }
 
+    /// Create a state which remembers `history_size` past record Merkle roots.
+    ///
+    /// A larger history lets validators accept transactions built against older states, at the
+    /// cost of memory. The history size is one of the [ChainVariables], so this is the same as
+    /// [new](Self::new) with `chain` built using
+    /// [with_record_root_history_size](ChainVariables::with_record_root_history_size).
+    pub fn new_with_history_size(
+        chain: ChainVariables,
+        record_merkle_frontier: MerkleTree,
+        stake_table_map_root: StakeTableCommitment,
+        total_stake: Amount,
+        stake_table_commitments_mt: StakeTableSetMT,
+        history_size: usize,
+    ) -> Self {
+        Self::new(
+            chain.with_record_root_history_size(history_size),
+            record_merkle_frontier,
+            stake_table_map_root,
+            total_stake,
+            stake_table_commitments_mt,
+        )
+    }
+
+    /// An empty state for a chain which uses `verif_crs` to verify transactions.
+    ///
+    /// The state has an empty record Merkle tree of height [MERKLE_HEIGHT], an empty stake table,
+    /// and otherwise default [ChainVariables] (chain ID 0 and committee size 0). Its commitment is
+    /// [genesis_commitment](Self::genesis_commitment)`(verif_crs)`. This is only the parent
+    /// commitment of a genesis block if `verif_crs` is the default [VERIF_CRS], since
+    /// [ElaboratedBlock::genesis] always builds on [ValidatorState::default]. To bootstrap a chain
+    /// from a genesis transaction, use [genesis](Self::genesis) instead.
+    pub fn from_verifier_keys(verif_crs: VerifierKeySet) -> Self {
+        Self::empty(ChainVariables::new(0, Arc::new(verif_crs), 0))
+    }
+
+    fn empty(chain: ChainVariables) -> Self {
+        Self::new(
+            chain,
+            MerkleTree::new(MERKLE_HEIGHT).unwrap(),
+            StakeTableCommitment(StakeTableMap::EmptySubtree.hash()),
+            Amount::from(0u64),
+            StakeTableSetMT::new(MERKLE_HEIGHT).unwrap(),
+        )
+    }
+
     pub fn genesis(txn: GenesisNote) -> Self {
         Self::default()
             .append(&ElaboratedBlock::genesis(txn), &ConsensusTime::genesis())
             .unwrap()
     }
 
+    /// The commitment to the empty state of a chain which uses `verif_crs`.
+    ///
+    /// This is the commitment of [from_verifier_keys](Self::from_verifier_keys)`(verif_crs)`, but
+    /// it is computed without building the state. With the default verifier keys, it is the parent
+    /// commitment of every genesis block (see [ElaboratedBlock::genesis]), so clients can compare it
+    /// with the genesis block reported by a query service to check that they are following a chain
+    /// with the expected verifier keys.
+    pub fn genesis_commitment(verif_crs: &VerifierKeySet) -> LedgerStateCommitment {
+        state_comm::LedgerCommitmentOpening::empty(verif_crs).commit()
+    }
+
+    /// Serialize this state as JSON.
+    ///
+    /// Components which are stored in canonical binary form, such as commitments and keys, are
+    /// encoded as tagged base64 strings, so the result is self-contained and can be read back with
+    /// [from_json](Self::from_json).
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    /// Deserialize a state produced by [to_json](Self::to_json).
+    pub fn from_json(s: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(s)
+    }
+
     /// Cryptographic commitment to the validator state
     pub fn commit(&self) -> LedgerStateCommitment {
         Committable::commit(self).into()
     }
 
+    /// Check whether `expected` is the commitment to this state.
+    pub fn verify_commitment(&self, expected: &LedgerStateCommitment) -> bool {
+        self.commit() == *expected
+    }
+
+    /// The values committed to by the state commitment.
+    ///
+    /// This is mainly useful for debugging: when two nodes disagree on a state commitment,
+    /// comparing their (serialized) openings shows which part of the state they disagree on.
+    pub fn commitment_opening(&self) -> state_comm::LedgerCommitmentOpening {
+        state_comm::LedgerCommitmentOpening::from_validator_state(self)
+    }
+
+    /// The maximum number of past record Merkle roots this state remembers.
+    pub fn record_root_history_size(&self) -> usize {
+        self.chain.record_root_history_size
+    }
+
+    /// The global variables of the chain this state belongs to.
+    pub fn chain(&self) -> &ChainVariables {
+        &self.chain
+    }
+
+    /// Replace the chain variables, invalidating the cached commitments which depend on them.
+    pub fn set_chain(&mut self, chain: ChainVariables) {
+        self.chain = chain;
+        self.chain_commitment = OnceCell::new();
+        self.verif_crs_commitment = OnceCell::new();
+    }
+
+    /// The unique identifier of the chain this state belongs to.
+    ///
+    /// The chain ID is part of [ChainVariables], so states of different chains have different
+    /// commitments even if they are otherwise identical.
+    pub fn chain_id(&self) -> u16 {
+        self.chain.chain_id
+    }
+
+    /// The total number of nullifiers spent on this chain.
+    ///
+    /// The count is maintained by [NullifierHistory] and is part of the state commitment.
+    pub fn nullifier_count(&self) -> u64 {
+        self.past_nullifiers.count() as u64
+    }
+
+    /// The changes made to `previous` to reach this state.
+    ///
+    /// `previous` must be a recent ancestor of this state: at most
+    /// [HISTORY_SIZE](Self::HISTORY_SIZE) blocks older, since only that many blocks of nullifiers
+    /// are remembered. Returns [None] if the nullifiers spent since `previous` cannot be determined,
+    /// including when `previous` is not an ancestor of this state at all.
+    pub fn state_diff(&self, previous: &ValidatorState) -> Option<StateDiff> {
+        let num_blocks = self.block_height.checked_sub(previous.block_height)?;
+        let (root, new_nullifiers) = self
+            .past_nullifiers
+            .last_blocks(num_blocks.try_into().ok()?)?;
+        if root != previous.past_nullifiers.current_root()
+            || self.record_merkle_commitment.num_leaves
+                < previous.record_merkle_commitment.num_leaves
+        {
+            return None;
+        }
+        Some(StateDiff {
+            new_nullifiers,
+            first_record_uid: previous.record_merkle_commitment.num_leaves,
+            record_merkle_commitment: self.record_merkle_commitment,
+            record_merkle_frontier: self.record_merkle_frontier.clone(),
+            commitment: self.commit(),
+        })
+    }
+
+    /// The time at which the most recent block was committed.
+    pub fn time(&self) -> ConsensusTime {
+        self.prev_commit_time
+    }
+
+    /// The time one view after the most recent block was committed.
+    ///
+    /// This is not a lower bound: [validate_block_check](Self::validate_block_check) only rejects
+    /// blocks which are older than [time](Self::time), so the next block may also be committed at
+    /// `time()` itself.
+    pub fn next_time(&self) -> ConsensusTime {
+        self.prev_commit_time + 1
+    }
+
+    /// The root hash of the current record Merkle tree.
+    ///
+    /// Transactions prove that their inputs are records in the tree with this root, or with one of
+    /// the recent roots in [past_record_merkle_roots](Self::past_record_merkle_roots). Transactions
+    /// built against an older root are rejected with [ValidationError::BadMerkleRoot].
+    pub fn record_merkle_root(&self) -> NodeValue {
+        self.record_merkle_commitment.root_value
+    }
+
+    /// The total number of records ever created on this chain.
+    ///
+    /// This is the number of leaves in the record Merkle tree, which also serves as the UID of the
+    /// next record to be created.
+    pub fn record_count(&self) -> u64 {
+        self.record_merkle_commitment.num_leaves
+    }
+
+    /// Commitment to the verifier keys used by this chain.
+    ///
+    /// This is computed the first time it is needed and cached.
+    pub fn verif_crs_commitment(&self) -> Commitment<CommittableVerifierKeySet> {
+        *self
+            .verif_crs_commitment
+            .get_or_init(|| CommittableVerifierKeySet(self.chain.verif_crs.clone()).to_commitment())
+    }
+
+    /// Commitment to the chain variables, computed the first time it is needed and cached.
+    fn chain_commitment(&self) -> Commitment<ChainVariables> {
+        *self.chain_commitment.get_or_init(|| self.chain.commit())
+    }
+
+    /// Whether `block` is full, so that no more transactions should be added to it.
+    ///
+    /// A block is full if it already contains `max_txns` transactions, or if applying it to this
+    /// state would leave no room in the record Merkle tree for the outputs of another transaction.
+    /// (The nullifier set is a sparse Merkle tree indexed by hashes, so it cannot fill up.)
+    pub fn block_at_capacity(&self, block: &ElaboratedBlock, max_txns: usize) -> bool {
+        if block.len() >= max_txns {
+            return true;
+        }
+        // The record Merkle tree is a ternary tree.
+        let capacity = 3u64.pow(self.record_merkle_commitment.height as u32);
+        let new_records = block
+            .block
+            .0
+            .iter()
+            .map(|txn| txn.output_len() as u64)
+            .sum::<u64>();
+        self.record_merkle_commitment.num_leaves + new_records >= capacity
+    }
+
+    /// Check whether `nullifier` has been spent.
+    ///
+    /// `proof` must be a nullifier proof relative to this state's nullifier set or one of the
+    /// recent historical nullifier sets.
+    pub fn check_nullifier_status(
+        &self,
+        nullifier: Nullifier,
+        proof: &SetMerkleProof,
+    ) -> NullifierStatus {
+        let recent_nullifiers = self.past_nullifiers.recent_nullifiers();
+        match self
+            .past_nullifiers
+            .check_unspent(&recent_nullifiers, proof, nullifier)
+        {
+            Ok(_) => NullifierStatus::Unspent,
+            Err(ValidationError::NullifierAlreadyExists { .. }) => NullifierStatus::Spent,
+            Err(_) => NullifierStatus::InvalidProof,
+        }
+    }
+
     pub fn nullifiers_root(&self) -> set_hash::Hash {
         self.past_nullifiers.current_root()
     }
@@ -1454,6 +3151,20 @@ impl ValidatorState {
         parent_state: LedgerStateCommitment,
         txns: Block,
         txns_helper_proofs: Vec<EspressoTxnHelperProofs>,
+    ) -> Result<(Block, NullifierProofs, CollectedRewardsProofs), ValidationError> {
+        self.check_block(now, parent_state, txns, txns_helper_proofs, true)
+    }
+
+    /// Validate a block, optionally skipping verification of the CAP transaction proofs.
+    ///
+    /// See [validate_block_check](Self::validate_block_check).
+    fn check_block(
+        &self,
+        now: &ConsensusTime,
+        parent_state: LedgerStateCommitment,
+        txns: Block,
+        txns_helper_proofs: Vec<EspressoTxnHelperProofs>,
+        verify_proofs: bool,
     ) -> Result<(Block, NullifierProofs, CollectedRewardsProofs), ValidationError> {
         // The block must be intended for this state.
         if parent_state != self.commit() {
@@ -1524,39 +3235,37 @@ impl ValidatorState {
                 nullifiers_proofs.push((n, pf, root));
                 nulls.insert(n);
             }
+            tracing::debug!("nullifier checks passed for {} nullifiers", nulls.len());
 
             let verif_keys = cap_txns
                 .iter()
-                .map(|txn| match txn {
-                    TransactionNote::Mint(_) => Ok(&self.chain.verif_crs.mint),
-                    TransactionNote::Transfer(note) => {
-                        let num_inputs = note.inputs_nullifiers.len();
-                        let num_outputs = note.output_commitments.len();
-                        self.chain
+                .map(|txn| {
+                    let (num_inputs, num_outputs) = txn_size_class(txn);
+                    match txn {
+                        TransactionNote::Mint(_) => Ok(&self.chain.verif_crs.mint),
+                        TransactionNote::Transfer(_) => self
+                            .chain
                             .verif_crs
                             .xfr
                             .key_for_size(num_inputs, num_outputs)
                             .ok_or(UnsupportedTransferSize {
                                 num_inputs,
                                 num_outputs,
-                            })
-                    }
-                    TransactionNote::Freeze(note) => {
-                        let num_inputs = note.input_nullifiers.len();
-                        let num_outputs = note.output_commitments.len();
-                        self.chain
+                            }),
+                        TransactionNote::Freeze(_) => self
+                            .chain
                             .verif_crs
                             .freeze
                             .key_for_size(num_inputs, num_outputs)
-                            .ok_or(UnsupportedFreezeSize { num_inputs })
+                            .ok_or(UnsupportedFreezeSize { num_inputs }),
                     }
                 })
                 .collect::<Result<Vec<_>, _>>()?;
             let mut merkle_roots = vec![];
             for cap_note in cap_txns.iter() {
                 let note_mt_root = cap_note.merkle_root();
-                if self.record_merkle_commitment.root_value == note_mt_root
-                    || self.past_record_merkle_roots.0.contains(&note_mt_root)
+                if self.record_merkle_root() == note_mt_root
+                    || self.past_record_merkle_roots.contains(&note_mt_root)
                 {
                     merkle_roots.push(note_mt_root)
                 } else {
@@ -1564,9 +3273,10 @@ impl ValidatorState {
                 }
             }
             // cap transactions validates first
-            if !cap_txns.is_empty() {
+            if verify_proofs && !cap_txns.is_empty() {
                 txn_batch_verify(&cap_txns[..], &merkle_roots, self.block_height, &verif_keys)
-                    .map_err(|err| CryptoError { err: Ok(err) })?;
+                    .map_err(ValidationError::crypto)?;
+                tracing::debug!("verified proofs for {} CAP transactions", cap_txns.len());
             }
         }
 
@@ -1629,6 +3339,150 @@ impl ValidatorState {
         Ok((Block(txns), nullifiers_proofs, verified_rewards_proofs))
     }
 
+    /// Validate each transaction in a block individually.
+    ///
+    /// Unlike [validate_block_check](Self::validate_block_check), which stops at the first invalid
+    /// transaction, this returns one result for each transaction in `txns`, so that callers can
+    /// find all of the bad transactions in a block and resubmit the rest. Each transaction is
+    /// checked against this state and against the transactions before it in the block, so a
+    /// transaction which spends a nullifier or collects a reward that an earlier valid transaction
+    /// already spent or collected is reported as invalid.
+    ///
+    /// If the block as a whole is not valid for this state (because it has the wrong parent or an
+    /// invalid time) every transaction is reported with the same error.
+    pub fn validate_block_dry_run(
+        &self,
+        now: &ConsensusTime,
+        parent_state: LedgerStateCommitment,
+        txns: Block,
+        txns_helper_proofs: Vec<EspressoTxnHelperProofs>,
+    ) -> Vec<Result<(), ValidationError>> {
+        let block_error = if parent_state != self.commit() {
+            Some(ValidationError::IncorrectParent)
+        } else if *now < self.prev_commit_time {
+            Some(ValidationError::InvalidTime)
+        } else {
+            None
+        };
+        if let Some(err) = block_error {
+            return vec![Err(err); txns.0.len()];
+        }
+
+        let num_txns = txns.0.len();
+        let mut proofs = txns_helper_proofs.into_iter();
+        let mut nulls = HashSet::new();
+        let mut rewards = vec![];
+        txns.0
+            .into_iter()
+            .map(|txn| {
+                let proofs = proofs
+                    .next()
+                    .ok_or(ValidationError::MissingNullifierProof {})?;
+                if txn.is_genesis() && num_txns > 1 {
+                    return Err(ValidationError::UnexpectedGenesis);
+                }
+                self.validate_block_check(
+                    now,
+                    parent_state,
+                    Block(vec![txn.clone()]),
+                    vec![proofs],
+                )?;
+
+                // Check for conflicts with the transactions before this one in the block.
+                let txn_nulls = txn.input_nullifiers();
+                if let Some(nullifier) = txn_nulls.iter().find(|n| nulls.contains(*n)) {
+                    return Err(ValidationError::NullifierAlreadyExists {
+                        nullifier: *nullifier,
+                    });
+                }
+                if let EspressoTransaction::Reward(note) = &txn {
+                    let reward = CollectedRewards {
+                        staking_key: note.staking_key(),
+                        time: note.time(),
+                    };
+                    if rewards.contains(&reward) {
+                        return Err(ValidationError::RewardAlreadyCollected { reward });
+                    }
+                    rewards.push(reward);
+                }
+                nulls.extend(txn_nulls);
+                Ok(())
+            })
+            .collect()
+    }
+
+    /// Recover the state with commitment `target` by replaying `block_history` from this state.
+    ///
+    /// A state cannot be undone directly, so to roll back to an earlier state (for example, after a
+    /// fork), a validator keeps an older state, such as the last finalized state, and calls this
+    /// method on it. `block_history` contains the blocks applied after this state, in order, along
+    /// with the time at which each was applied. (The time is part of the state, so it must match
+    /// for the replayed states to have the same commitments as the originals.) Blocks are applied
+    /// until the commitment of the state equals `target`, so the history may extend past it.
+    ///
+    /// # Errors
+    /// - [RewindError::Unreachable] if the state after every block in `block_history` is not
+    ///   `target`
+    /// - [RewindError::InvalidBlock] if one of the blocks before `target` is not valid
+    pub fn rewind(
+        &self,
+        target: &LedgerStateCommitment,
+        block_history: &[(ElaboratedBlock, ConsensusTime)],
+    ) -> Result<ValidatorState, RewindError> {
+        let mut state = self.clone();
+        for (index, (block, time)) in block_history.iter().enumerate() {
+            if state.commit() == *target {
+                return Ok(state);
+            }
+            state
+                .validate_and_apply(
+                    time,
+                    block.parent_state,
+                    block.block.clone(),
+                    block.proofs.clone(),
+                )
+                .map_err(|source| RewindError::InvalidBlock { index, source })?;
+        }
+        if state.commit() == *target {
+            Ok(state)
+        } else {
+            Err(RewindError::Unreachable { target: *target })
+        }
+    }
+
+    /// Whether `block` could be applied to this state.
+    ///
+    /// This is a lightweight check with no side effects: it runs the same validation as
+    /// [validate_block_check](Self::validate_block_check), including proof verification, but
+    /// neither modifies this state nor builds the next one. The block is checked as if it were
+    /// committed at the same time as the previous block, so that only the contents of the block,
+    /// not the time, can cause it to be rejected.
+    pub fn can_apply_block(&self, block: &ElaboratedBlock) -> bool {
+        self.validate_block_check(
+            &self.time(),
+            block.parent_state,
+            block.block.clone(),
+            block.proofs.clone(),
+        )
+        .is_ok()
+    }
+
+    /// Validate each of `txns` independently against this state.
+    ///
+    /// Each transaction is checked (including its nullifier proofs and zero-knowledge proofs) as
+    /// if it were the only transaction in the next block. Unlike
+    /// [validate_block_dry_run](Self::validate_block_dry_run), transactions are not checked
+    /// against each other, so this is suitable for screening transactions before deciding which
+    /// of them to put in a block.
+    pub fn batch_validate_transactions(
+        &self,
+        txns: &[ElaboratedTransaction],
+    ) -> Vec<Result<(), ValidationError>> {
+        txns.iter()
+            .map(|txn| validate_transaction(self, &self.time(), txn))
+            .collect()
+    }
+
     /// Performs validation for a block, updating the ValidatorState.
     ///
     /// If successful, returns
@@ -1649,8 +3503,71 @@ impl ValidatorState {
         txns: Block,
         proofs: Vec<EspressoTxnHelperProofs>,
     ) -> Result<ValidationOutputs, ValidationError> {
-        let (txns, null_pfs, rewards) =
-            self.validate_block_check(now, parent_state, txns, proofs)?;
+        let span = tracing::debug_span!(
+            "validate_and_apply",
+            block_height = self.block_height,
+            num_txns = txns.0.len()
+        );
+        let _enter = span.enter();
+        let (txns, null_pfs, rewards) = self
+            .validate_block_check(now, parent_state, txns, proofs)
+            .map_err(|err| {
+                tracing::debug!("block is invalid: {}", err);
+                err
+            })?;
+        Ok(self.apply_checked_block(now, txns, null_pfs, rewards))
+    }
+
+    /// The state which would result from applying `block` to this state at time `now`.
+    ///
+    /// This validates and applies `block` exactly like
+    /// [validate_and_apply](Self::validate_and_apply), but to a copy of this state, leaving `self`
+    /// unchanged.
+    pub fn hypothetical_next_state(
+        &self,
+        now: &ConsensusTime,
+        block: &ElaboratedBlock,
+    ) -> Result<ValidatorState, ValidationError> {
+        let mut next = self.clone();
+        next.validate_and_apply(
+            now,
+            block.parent_state,
+            block.block.clone(),
+            block.proofs.clone(),
+        )?;
+        Ok(next)
+    }
+
+    /// Apply a block which is known to be valid, without verifying its transaction proofs.
+    ///
+    /// This is meant for replaying blocks which have already been validated, such as blocks loaded
+    /// from persistent storage, where re-verifying the zero-knowledge proofs would be prohibitively
+    /// expensive. All of the other checks in [validate_and_apply](Self::validate_and_apply) are
+    /// still performed, and the record Merkle tree, nullifier set and other state are updated
+    /// exactly as they would be by [validate_and_apply](Self::validate_and_apply).
+    ///
+    /// **Warning**: the caller must guarantee that this block has previously been successfully
+    /// validated against this exact state. Applying a block with invalid proofs corrupts the
+    /// ledger state.
+    #[doc(hidden)]
+    pub fn apply_block_unchecked(
+        &mut self,
+        now: &ConsensusTime,
+        parent_state: LedgerStateCommitment,
+        txns: Block,
+        proofs: Vec<EspressoTxnHelperProofs>,
+    ) -> Result<ValidationOutputs, ValidationError> {
+        let (txns, null_pfs, rewards) = self.check_block(now, parent_state, txns, proofs, false)?;
+        Ok(self.apply_checked_block(now, txns, null_pfs, rewards))
+    }
+
+    fn apply_checked_block(
+        &mut self,
+        now: &ConsensusTime,
+        txns: Block,
+        null_pfs: NullifierProofs,
+        rewards: CollectedRewardsProofs,
+    ) -> ValidationOutputs {
         // If the block successfully validates, and the nullifier proofs apply correctly, the
         // remaining (mutating) operations cannot fail, as this would result in an inconsistent
         // state. No operations after the first assignement to a member of self have a possible
@@ -1667,7 +3584,7 @@ impl ValidatorState {
 
         // If this is a genesis block, apply system parameter updates.
         if let Some(EspressoTransaction::Genesis(txn)) = txns.0.get(0) {
-            self.chain = txn.chain.clone();
+            self.set_chain(txn.chain.clone());
             let mut total_stake = Amount::from(0u128);
             let mut stake_table = KVMerkleTree::<StakeTableHash>::default();
             for (key, amount) in txn.stake_table.iter() {
@@ -1710,13 +3627,22 @@ impl ValidatorState {
         let record_merkle_frontier = record_merkle_builder.build();
         assert_eq!(uid, record_merkle_frontier.num_leaves());
 
-        if self.past_record_merkle_roots.0.len() >= Self::HISTORY_SIZE {
-            self.past_record_merkle_roots.0.pop_back();
+        let history_size = self.record_root_history_size();
+        if self.past_record_merkle_roots.len() >= history_size {
+            // This is the steady state once the chain is longer than the history, so it is not
+            // worth more than a trace.
+            tracing::trace!(
+                "record Merkle root history is at capacity ({}), dropping the oldest root",
+                history_size
+            );
         }
         self.past_record_merkle_roots
-            .0
-            .push_front(self.record_merkle_commitment.root_value);
+            .push_front_capped(self.record_merkle_root(), history_size);
         self.record_merkle_commitment = record_merkle_frontier.commitment();
+        tracing::debug!(
+            "record Merkle tree updated, now has {} leaves",
+            self.record_merkle_commitment.num_leaves
+        );
         self.record_merkle_frontier = record_merkle_frontier.frontier();
 
         // Build stake table commitments frontier, history and new commitment
@@ -1745,11 +3671,11 @@ impl ValidatorState {
             .append_block(rewards)
             .expect("failed to append collected rewards after validation");
         self.prev_state = Some(comm);
-        Ok(ValidationOutputs {
+        ValidationOutputs {
             uids,
             nullifier_proofs: null_pfs,
             record_proofs: record_merkle_frontier,
-        })
+        }
     }
 
     pub fn update_nullifier_proofs(
@@ -1793,6 +3719,170 @@ impl ValidatorState {
     }
 }
 
+/// The number of inputs and outputs of a CAP transaction.
+///
+/// This is the size used to select the proving or verifying key for the transaction. Mint
+/// transactions always have a single (fee) input and two outputs (the fee change and the minted
+/// record).
+pub fn txn_size_class(txn: &TransactionNote) -> (usize, usize) {
+    match txn {
+        TransactionNote::Mint(_) => (1, 2),
+        TransactionNote::Transfer(note) => {
+            (note.inputs_nullifiers.len(), note.output_commitments.len())
+        }
+        TransactionNote::Freeze(note) => {
+            (note.input_nullifiers.len(), note.output_commitments.len())
+        }
+    }
+}
+
+/// Whether `keys` contains a proving key which can be used to build `txn`.
+///
+/// This can be used to check that a transaction of a given shape can be proved before attempting
+/// the (expensive) proof generation. Every key set contains a mint key, so mint transactions are
+/// always supported.
+pub fn prover_supports_transaction(keys: &ProverKeySet, txn: &TransactionNote) -> bool {
+    let (num_inputs, num_outputs) = txn_size_class(txn);
+    match txn {
+        TransactionNote::Mint(_) => true,
+        TransactionNote::Transfer(_) => keys.xfr.key_for_size(num_inputs, num_outputs).is_some(),
+        TransactionNote::Freeze(_) => keys.freeze.key_for_size(num_inputs, num_outputs).is_some(),
+    }
+}
+
+/// Validate a single transaction against `state`, as if it were the only transaction in a block at
+/// time `now`.
+///
+/// This performs the same checks as [ValidatorState::validate_block_check], including the
+/// nullifier proofs and zero-knowledge proofs, without the caller having to build a block. The
+/// cheap checks are done first, so a transaction which fails one of them is rejected without
+/// building a block or verifying any zero-knowledge proofs.
+pub fn validate_transaction(
+    state: &ValidatorState,
+    now: &ConsensusTime,
+    txn: &ElaboratedTransaction,
+) -> Result<(), ValidationError> {
+    if *now < state.prev_commit_time {
+        return Err(ValidationError::InvalidTime);
+    }
+    match (&txn.txn, &txn.proofs) {
+        (EspressoTransaction::Genesis(_), _) => {
+            if state.block_height != 0 {
+                return Err(ValidationError::UnexpectedGenesis);
+            }
+        }
+        (EspressoTransaction::CAP(note), EspressoTxnHelperProofs::CAP(proofs)) => {
+            let recent_nullifiers = state.past_nullifiers.recent_nullifiers();
+            for (proof, nullifier) in proofs.iter().zip(note.nullifiers()) {
+                state
+                    .past_nullifiers
+                    .check_unspent(&recent_nullifiers, proof, nullifier)?;
+            }
+        }
+        (EspressoTransaction::Reward(_), EspressoTxnHelperProofs::Reward(_)) => {}
+        _ => return Err(ValidationError::InconsistentHelperProofs),
+    }
+
+    state.validate_block_check(
+        now,
+        state.commit(),
+        Block(vec![txn.txn.clone()]),
+        vec![txn.proofs.clone()],
+    )?;
+    Ok(())
+}
+
+/// Whether `keys` contains a verifying key which can be used to validate `txn`.
+///
+/// Checking this against the verifying keys of the current network (see [ChainVariables]) before
+/// building a transaction avoids generating proofs for a transaction that the network will reject
+/// with [ValidationError::UnsupportedTransferSize] or [ValidationError::UnsupportedFreezeSize].
+pub fn verifier_supports_transaction(keys: &VerifierKeySet, txn: &TransactionNote) -> bool {
+    let (num_inputs, num_outputs) = txn_size_class(txn);
+    match txn {
+        TransactionNote::Mint(_) => true,
+        TransactionNote::Transfer(_) => keys.xfr.key_for_size(num_inputs, num_outputs).is_some(),
+        TransactionNote::Freeze(_) => keys.freeze.key_for_size(num_inputs, num_outputs).is_some(),
+    }
+}
+
+/// A transaction size for which one of a pair of key sets has a key, but the other does not.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum KeySizeMismatch {
+    /// There is a proving key, but no verifying key, for transactions of this size.
+    MissingVerifyingKey {
+        kind: TransactionKind,
+        num_inputs: usize,
+        num_outputs: usize,
+    },
+    /// There is a verifying key, but no proving key, for transactions of this size.
+    MissingProvingKey {
+        kind: TransactionKind,
+        num_inputs: usize,
+        num_outputs: usize,
+    },
+}
+
+/// Check that `prover` and `verifier` have keys for exactly the same transaction sizes.
+///
+/// Loading mismatched key sets causes transactions to fail much later, when they are proved or
+/// validated, so this can be used to catch the problem at startup instead. Every key set contains
+/// exactly one mint key, so only transfer and freeze keys are compared.
+pub fn check_key_sets_compatible(
+    prover: &ProverKeySet,
+    verifier: &VerifierKeySet,
+) -> Result<(), Vec<KeySizeMismatch>> {
+    let mut mismatches = vec![];
+    let mut compare = |kind, proving: BTreeSet<(usize, usize)>, verifying: BTreeSet<_>| {
+        for &(num_inputs, num_outputs) in proving.difference(&verifying) {
+            mismatches.push(KeySizeMismatch::MissingVerifyingKey {
+                kind,
+                num_inputs,
+                num_outputs,
+            });
+        }
+        for &(num_inputs, num_outputs) in verifying.difference(&proving) {
+            mismatches.push(KeySizeMismatch::MissingProvingKey {
+                kind,
+                num_inputs,
+                num_outputs,
+            });
+        }
+    };
+    compare(
+        TransactionKind::Transfer,
+        prover
+            .xfr
+            .iter()
+            .map(|k| (k.num_inputs(), k.num_outputs()))
+            .collect(),
+        verifier
+            .xfr
+            .iter()
+            .map(|k| (k.num_inputs(), k.num_outputs()))
+            .collect(),
+    );
+    compare(
+        TransactionKind::Freeze,
+        prover
+            .freeze
+            .iter()
+            .map(|k| (k.num_inputs(), k.num_outputs()))
+            .collect(),
+        verifier
+            .freeze
+            .iter()
+            .map(|k| (k.num_inputs(), k.num_outputs()))
+            .collect(),
+    );
+
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(mismatches)
+    }
+}
+
 /// converts Amount to NonZeroU64
 pub fn amount_to_nonzerou64(amt: Amount) -> NonZeroU64 {
     (u128::from(amt) as u64).try_into().unwrap()
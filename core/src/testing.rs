@@ -194,7 +194,7 @@ impl MultiXfrTestState {
     }
 
     pub fn next_view(&self) -> ConsensusTime {
-        self.validator.prev_commit_time + 1
+        self.validator.next_time()
     }
 
     /// Creates test state with initial records.
@@ -469,6 +469,7 @@ impl MultiXfrTestState {
                         txn: EspressoTransaction::CAP(TransactionNote::Mint(Box::new(note))),
                         proofs: EspressoTxnHelperProofs::CAP(vec![nul]),
                         memos: Some((memos, signature)),
+                        memo: None,
                     },
                     ix,
                     vec![kix, kix],
@@ -480,7 +481,7 @@ impl MultiXfrTestState {
             keys_in_block.clear();
             ret.validate_and_apply(
                 setup_block,
-                &(ret.validator.prev_commit_time + 1),
+                &ret.validator.next_time(),
                 0.0,
                 TxnPrintInfo::new_no_time(0, 0),
             )
@@ -491,7 +492,7 @@ impl MultiXfrTestState {
 
         ret.validate_and_apply(
             setup_block,
-            &(ret.validator.prev_commit_time + 1),
+            &ret.validator.next_time(),
             0.0,
             TxnPrintInfo::new_no_time(0, 0),
         )
@@ -918,6 +919,7 @@ impl MultiXfrTestState {
                         txn: EspressoTransaction::CAP(TransactionNote::Transfer(Box::new(txn))),
                         proofs: EspressoTxnHelperProofs::CAP(nullifier_pfs),
                         memos: Some((owner_memos, sig)),
+                        memo: None,
                     },
                 })
             })
@@ -1079,6 +1081,7 @@ impl MultiXfrTestState {
                 txn: EspressoTransaction::CAP(TransactionNote::Transfer(Box::new(txn))),
                 proofs: EspressoTxnHelperProofs::CAP(nullifier_pfs),
                 memos: Some((owner_memos, sig)),
+                memo: None,
             },
         })
     }
@@ -1233,19 +1236,29 @@ pub fn crypto_rng_from_seed(seed: [u8; 32]) -> ChaChaRng {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::genesis::GenesisNote;
     use crate::stake_table::{StakeTableMap, StakeTableSetMT};
     use async_std::sync::Arc;
     use commit::Committable;
     use jf_cap::structs::{NoteType, Nullifier};
     use jf_cap::{
-        utils::compute_universal_param_size, BaseField, MerkleLeafProof, NodeValue,
-        TransactionVerifyingKey,
+        errors::TxnApiError, utils::compute_universal_param_size, BaseField, MerkleLeafProof,
+        NodeValue, TransactionVerifyingKey,
     };
     use jf_primitives::merkle_tree::LookupResult;
     use key_set::KeySet;
     use quickcheck::QuickCheck;
     use rand::{Rng, RngCore};
+    use serde::{de::DeserializeOwned, Serialize};
+    use static_assertions::assert_impl_all;
     use std::cmp::min;
+    use std::collections::VecDeque;
+
+    // These types are shared across tasks and threads, so make sure no dependency ever makes them
+    // thread-unsafe.
+    assert_impl_all!(ValidatorState: Send, Sync);
+    assert_impl_all!(ElaboratedBlock: Send, Sync);
+    assert_impl_all!(VerifierKeySet: Send, Sync);
 
     #[test]
     fn multixfr_setup() {
@@ -1487,14 +1500,80 @@ mod tests {
         let mut v2 = v1.clone();
 
         // Test validators with different history lengths.
-        v1.past_record_merkle_roots.0.push_front(NodeValue::from(0));
+        v1.past_record_merkle_roots.push_front(NodeValue::from(0));
         assert_ne!(v1.commit(), v2.commit());
 
         // Test validators with the same length, but different histories.
-        v2.past_record_merkle_roots.0.push_front(NodeValue::from(1));
+        v2.past_record_merkle_roots.push_front(NodeValue::from(1));
         assert_ne!(v1.commit(), v2.commit());
     }
 
+    #[test]
+    fn test_record_history_size_commit_hash() {
+        // Check that ValidatorStates with different record history sizes have different commits.
+        let validator = |history_size| {
+            ValidatorState::new_with_history_size(
+                ChainVariables::new(42, VERIF_CRS.clone(), SORTITION_PARAMETER),
+                MerkleTree::new(MERKLE_HEIGHT).unwrap(),
+                StakeTableCommitment(StakeTableMap::EmptySubtree.hash()),
+                Amount::from(0u64),
+                StakeTableSetMT::new(MERKLE_HEIGHT).unwrap(),
+                history_size,
+            )
+        };
+        assert_ne!(
+            validator(ValidatorState::HISTORY_SIZE).commit(),
+            validator(ValidatorState::HISTORY_SIZE + 1).commit()
+        );
+    }
+
+    #[test]
+    fn test_record_history_size_genesis() {
+        // The history size is set by the genesis transaction, so every node of a chain uses it.
+        let chain = ChainVariables::default().with_record_root_history_size(3);
+        let state = ValidatorState::genesis(GenesisNote::new(
+            chain.clone(),
+            Arc::new(vec![]),
+            Default::default(),
+        ));
+        assert_eq!(state.record_root_history_size(), 3);
+
+        // Chain variables serialized without a history size get the default.
+        let mut json = serde_json::to_value(&chain).unwrap();
+        json.as_object_mut()
+            .unwrap()
+            .remove("record_root_history_size")
+            .unwrap();
+        let chain: ChainVariables = serde_json::from_value(json).unwrap();
+        assert_eq!(chain.record_root_history_size, ValidatorState::HISTORY_SIZE);
+    }
+
+    #[test]
+    fn test_chain_variables_canonical_encoding() {
+        // The history size is appended to the canonical encoding of the chain variables, after all
+        // of the other fields.
+        let chain = ChainVariables::default().with_record_root_history_size(3);
+        let bytes = canonical::serialize(&chain).unwrap();
+        let mut expected = vec![];
+        expected.extend(canonical::serialize(&chain.protocol_version).unwrap());
+        expected.extend(canonical::serialize(&chain.chain_id).unwrap());
+        expected.extend(canonical::serialize(&chain.verif_crs).unwrap());
+        expected.extend(canonical::serialize(&chain.vrf_seed).unwrap());
+        expected.extend(canonical::serialize(&chain.committee_size).unwrap());
+        expected.extend(3u64.to_le_bytes());
+        assert_eq!(bytes, expected);
+
+        let decoded: ChainVariables = canonical::deserialize(&bytes).unwrap();
+        assert_eq!(decoded.record_root_history_size, 3);
+        assert_eq!(decoded.commit(), chain.commit());
+        // Encodings from before the history size was added are rejected.
+        assert!(canonical::deserialize::<ChainVariables>(&bytes[..bytes.len() - 8]).is_err());
+
+        // The chain variables come first in the encoding of a genesis note.
+        let genesis = GenesisNote::new(chain, Arc::new(vec![]), Default::default());
+        assert!(canonical::serialize(&genesis).unwrap().starts_with(&bytes));
+    }
+
     // Test historical nullifier verification. Builds two transactions against the same state but
     // submits them in two sequential blocks, so that the second transaction must be validated
     // against a historical nullifier set.
@@ -1543,13 +1622,20 @@ mod tests {
         for (i, tx) in txns.into_iter().enumerate() {
             let kixs = tx.keys_and_memos.into_iter().map(|(kix, _)| kix).collect();
             let mut blk = state.validator.next_block();
-            let _ = state.try_add_transaction(
-                &mut blk,
-                tx.transaction,
-                tx.index,
-                kixs,
-                TxnPrintInfo::new_no_time(i, 2),
-            );
+            let added = state
+                .try_add_transaction(
+                    &mut blk,
+                    tx.transaction,
+                    tx.index,
+                    kixs,
+                    TxnPrintInfo::new_no_time(i, 2),
+                )
+                .is_ok();
+            // Each generated transaction pays a fee of 1.
+            assert_eq!(blk.total_fee(), if added { 1 } else { 0 });
+            blk.block
+                .is_valid_against_keyset(&state.verif_keys)
+                .unwrap();
             let res = state.validate_and_apply(
                 blk,
                 &state.next_view(),
@@ -1722,7 +1808,7 @@ mod tests {
 
         let new_uids = validator
             .validate_and_apply(
-                &(validator.prev_commit_time + 1),
+                &validator.next_time(),
                 validator.commit(),
                 Block(vec![EspressoTransaction::CAP(TransactionNote::Transfer(
                     Box::new(txn1),
@@ -2154,4 +2240,940 @@ mod tests {
             .tests(5)
             .quickcheck(test_nullifier_history_commitment as fn(u64, Vec<_>) -> ());
     }
+
+    #[test]
+    fn test_elaborated_block_from_raw_parts() {
+        let parent = ValidatorState::default().commit();
+
+        let block = ElaboratedBlock::from_raw_parts(parent, Block(vec![]), vec![]).unwrap();
+        assert!(block.is_empty());
+        assert_eq!(block, ElaboratedBlock::new(parent));
+
+        assert!(matches!(
+            ElaboratedBlock::from_raw_parts(
+                parent,
+                Block(vec![]),
+                vec![EspressoTxnHelperProofs::Genesis]
+            ),
+            Err(ValidationError::MissingNullifierProof {})
+        ));
+    }
+
+    /// Check that `val` survives a round trip through both JSON and binary serialization.
+    ///
+    /// Not all of the types we check implement [PartialEq], so we compare the re-serialized bytes
+    /// rather than the values themselves.
+    fn check_serde_round_trip<T: Serialize + DeserializeOwned>(val: &T) {
+        let json = serde_json::to_string(val).unwrap();
+        let from_json: T = serde_json::from_str(&json).unwrap();
+        assert_eq!(json, serde_json::to_string(&from_json).unwrap());
+
+        let bytes = bincode::serialize(val).unwrap();
+        let from_bytes: T = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(bytes, bincode::serialize(&from_bytes).unwrap());
+    }
+
+    #[test]
+    fn test_state_serde_round_trip() {
+        let genesis = GenesisNote::empty();
+        let txn = ElaboratedTransaction {
+            txn: EspressoTransaction::Genesis(genesis.clone()),
+            proofs: EspressoTxnHelperProofs::Genesis,
+            memos: None,
+            memo: None,
+        };
+        let state = ValidatorState::genesis(genesis.clone());
+        let mut history = RecordMerkleHistory::default();
+        history.push_front(NodeValue::from(0));
+        history.push_front(NodeValue::from(1));
+
+        check_serde_round_trip(&txn);
+        check_serde_round_trip(&ElaboratedBlock::genesis(genesis));
+        check_serde_round_trip(&ElaboratedBlock::new(state.commit()));
+        check_serde_round_trip(&state);
+        check_serde_round_trip(&ValidatorState::default());
+        check_serde_round_trip(&**VERIF_CRS);
+        check_serde_round_trip(&history);
+    }
+
+    #[test]
+    fn test_record_history_push_front_capped() {
+        let mut history = RecordMerkleHistory::default();
+        for i in 0..3 {
+            history.push_front_capped(NodeValue::from(i), 2);
+        }
+        assert_eq!(
+            history.iter().cloned().collect::<Vec<_>>(),
+            vec![NodeValue::from(2), NodeValue::from(1)]
+        );
+        assert!(!history.contains(&NodeValue::from(0)));
+
+        // A repeated root is only forgotten once all of its occurrences have been dropped.
+        history.push_front_capped(NodeValue::from(2), 2);
+        assert_eq!(history.len(), 2);
+        assert!(!history.contains(&NodeValue::from(1)));
+        history.push_front_capped(NodeValue::from(3), 2);
+        assert!(history.contains(&NodeValue::from(2)));
+        history.push_front_capped(NodeValue::from(4), 2);
+        assert!(!history.contains(&NodeValue::from(2)));
+
+        // The index kept up to date by each push matches one built from scratch.
+        let mut rng = ChaChaRng::from_seed([0x44; 32]);
+        for _ in 0..100 {
+            history.push_front_capped(NodeValue::from(rng.gen_range(0u64..5)), 3);
+            assert!(history.len() <= 3);
+            let rebuilt = RecordMerkleHistory::from(VecDeque::from(history.clone()));
+            for i in 0..5 {
+                assert_eq!(
+                    history.contains(&NodeValue::from(i)),
+                    rebuilt.contains(&NodeValue::from(i))
+                );
+            }
+        }
+        history.push_front_capped(NodeValue::from(5), 0);
+        assert!(history.is_empty());
+        assert!(!history.contains(&NodeValue::from(5)));
+    }
+
+    #[test]
+    fn test_empty_block_commitment() {
+        // `Block` on its own names the HotShot trait here, so qualify the state's block type.
+        use crate::state::Block;
+        assert_eq!(Block::empty_commitment(), Block(vec![]).commit());
+        assert_eq!(
+            ValidatorState::default().prev_block,
+            Block::empty_commitment()
+        );
+    }
+
+    #[test]
+    fn test_state_json_round_trip() {
+        let genesis = GenesisNote::empty();
+        for state in [ValidatorState::default(), ValidatorState::genesis(genesis)] {
+            let json = state.to_json().unwrap();
+            assert_eq!(ValidatorState::from_json(&json).unwrap(), state);
+        }
+        assert!(ValidatorState::from_json("{}").is_err());
+    }
+
+    #[test]
+    fn test_transaction_commitment_hex() {
+        let comm = TransactionCommitment(ElaboratedTransaction::default().txn.commit());
+        let hex = format!("{:x}", comm);
+        assert_eq!(hex.len(), 64);
+        assert_eq!(hex, hex.to_lowercase());
+        assert_eq!(format!("{:X}", comm), hex.to_uppercase());
+        assert_eq!(format!("{:#x}", comm), format!("0x{}", hex));
+    }
+
+    #[test]
+    fn test_block_size_bytes() {
+        let genesis = GenesisNote::empty();
+        let state = ValidatorState::genesis(genesis.clone());
+        for block in [
+            ElaboratedBlock::new(state.commit()),
+            ElaboratedBlock::genesis(genesis),
+        ] {
+            assert_eq!(
+                block.size_bytes(),
+                canonical::serialize(&block).unwrap().len()
+            );
+        }
+    }
+
+    #[test]
+    fn test_verifier_key_set_hash() {
+        use crate::state::state_comm::CommittableVerifierKeySet;
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        fn hash(keys: &CommittableVerifierKeySet) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            keys.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        // Two separately allocated copies of the same keys.
+        let keys1 = CommittableVerifierKeySet(VERIF_CRS.clone().into());
+        let keys2 = CommittableVerifierKeySet(
+            canonical::deserialize(&canonical::serialize(&keys1.0).unwrap()).unwrap(),
+        );
+        assert_eq!(keys1, keys2);
+        assert_eq!(hash(&keys1), hash(&keys2));
+    }
+
+    #[test]
+    fn test_public_validation_error() {
+        let err = ValidationError::crypto(TxnApiError::InvalidParameter("bad note".into()));
+        let public = PublicValidationError::from(err);
+        assert_eq!(
+            public.to_string(),
+            format!(
+                "cryptographic error: {}",
+                TxnApiError::InvalidParameter("bad note".into())
+            )
+        );
+        assert_eq!(
+            public,
+            PublicValidationError::CryptoError {
+                err: TxnApiError::InvalidParameter("bad note".into()).to_string(),
+                code: CryptoErrorCode::MalformedNote,
+            }
+        );
+        check_serde_round_trip(&public);
+
+        let err = ValidationError::BlockTooLarge {
+            num_txns: 10,
+            max: 5,
+        };
+        assert_eq!(
+            PublicValidationError::from(err.clone()).to_string(),
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_crypto_error_source() {
+        use std::error::Error;
+
+        let inner = TxnApiError::InvalidParameter("bad note".into());
+        let msg = inner.to_string();
+        let err = ValidationError::crypto(inner);
+
+        // The original error can be recovered from the error chain.
+        let wrapper = err.source().unwrap();
+        let source = wrapper.source().unwrap();
+        assert!(matches!(
+            source.downcast_ref::<TxnApiError>(),
+            Some(TxnApiError::InvalidParameter(param)) if param == "bad note"
+        ));
+        // The message of the original error is shown without walking the chain.
+        assert_eq!(err.to_string(), format!("cryptographic error: {}", msg));
+        assert_eq!(wrapper.to_string(), msg);
+
+        // A deserialized error only has a description of the original error.
+        let deserialized: ValidationError =
+            serde_json::from_str(&serde_json::to_string(&err).unwrap()).unwrap();
+        assert!(deserialized.to_string().contains(&msg));
+        let wrapper = deserialized.source().unwrap();
+        assert_eq!(wrapper.to_string(), msg);
+        assert!(wrapper.source().is_none());
+    }
+
+    #[test]
+    fn test_crypto_error_clone() {
+        let inner = TxnApiError::InvalidParameter("bad note".into());
+        let msg = inner.to_string();
+        let err = ValidationError::crypto(inner);
+
+        // Cloning keeps the variant, the code and the description of the original error.
+        let cloned = err.clone();
+        match (&err, &cloned) {
+            (
+                ValidationError::CryptoError { code, .. },
+                ValidationError::CryptoError {
+                    err: cloned_err,
+                    code: cloned_code,
+                },
+            ) => {
+                assert_eq!(cloned_code, code);
+                assert_eq!(cloned_err.message(), msg);
+            }
+            _ => panic!("expected CryptoError, got {:?}", cloned),
+        }
+        assert_eq!(cloned.to_string(), err.to_string());
+        assert_eq!(cloned.is_permanent(), err.is_permanent());
+
+        // A clone of a clone is the same.
+        assert_eq!(cloned.clone().to_string(), err.to_string());
+    }
+
+    #[test]
+    fn test_validation_error_permanence() {
+        let permanent = [
+            ValidationError::crypto(TxnApiError::InvalidParameter("bad note".into())),
+            ValidationError::UnsupportedFreezeSize { num_inputs: 5 },
+            ValidationError::Failed {},
+        ];
+        for err in permanent {
+            assert!(err.is_permanent(), "{:?}", err);
+            assert!(!err.is_transient(), "{:?}", err);
+        }
+
+        let transient = [
+            ValidationError::BadMerkleRoot {},
+            ValidationError::BadNullifierProof {},
+            ValidationError::ConflictingNullifiers {},
+            ValidationError::BlockTooLarge {
+                num_txns: 10,
+                max: 5,
+            },
+        ];
+        for err in transient {
+            assert!(err.is_transient(), "{:?}", err);
+            assert!(!err.is_permanent(), "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn test_state_from_verifier_keys() {
+        let state = ValidatorState::from_verifier_keys((*VERIF_CRS).clone());
+        assert_eq!(state.commit(), ValidatorState::default().commit());
+        assert_eq!(state.record_count(), 0);
+    }
+
+    #[test]
+    fn test_genesis_commitment() {
+        let commitment = ValidatorState::genesis_commitment(&VERIF_CRS);
+        assert_eq!(
+            commitment,
+            ValidatorState::from_verifier_keys((*VERIF_CRS).clone()).commit()
+        );
+
+        // With the default verifier keys, this is the state every genesis block is applied to.
+        let genesis = ElaboratedBlock::genesis(GenesisNote::empty());
+        assert_eq!(genesis.parent_state, commitment);
+    }
+
+    #[test]
+    fn test_transaction_memo_commitment() {
+        let mut txn = ElaboratedTransaction::default();
+        let commitment = txn.commit();
+        txn.memo = Some(b"routing hint".to_vec());
+        // The memo is off-chain metadata, so it does not change the identity of the transaction.
+        assert_eq!(txn.commit(), commitment);
+
+        // A transaction submitted with a memo can be found in the block which includes it, even
+        // though the block does not carry the memo.
+        let block = ElaboratedBlock::new(ValidatorState::default().commit())
+            .add_transaction_raw(&txn)
+            .unwrap();
+        assert!(block.contained_transactions().contains(&txn.commit()));
+
+        // The memo is kept by serde, but not by the canonical encoding.
+        check_serde_round_trip(&txn);
+        let decoded: ElaboratedTransaction =
+            canonical::deserialize(&canonical::serialize(&txn).unwrap()).unwrap();
+        assert_eq!(decoded.memo, None);
+        assert_eq!(decoded.commit(), commitment);
+    }
+
+    #[test]
+    fn test_chain_commitment_cache() {
+        let chain = ChainVariables::new(1, VERIF_CRS.clone(), 5);
+        let mut state = ValidatorState::default();
+        // Fill the caches before the genesis block replaces the chain variables.
+        let before = state.commit();
+        let default_keys = state.verif_crs_commitment();
+        state
+            .validate_and_apply(
+                &ConsensusTime::genesis(),
+                before,
+                Block(vec![EspressoTransaction::Genesis(GenesisNote::new(
+                    chain.clone(),
+                    Arc::new(vec![]),
+                    Default::default(),
+                ))]),
+                vec![EspressoTxnHelperProofs::Genesis],
+            )
+            .unwrap();
+        assert_eq!(state.commitment_opening().chain, chain.commit());
+        assert_eq!(state.verif_crs_commitment(), default_keys);
+
+        // The caches are not serialized, but they are recomputed after deserialization.
+        let json = state.to_json().unwrap();
+        assert!(!json.contains("chain_commitment"));
+        let deserialized = ValidatorState::from_json(&json).unwrap();
+        assert_eq!(deserialized.commit(), state.commit());
+        assert_eq!(deserialized.verif_crs_commitment(), default_keys);
+    }
+
+    #[test]
+    fn test_set_chain() {
+        let mut state = ValidatorState::default();
+        // Fill the caches before replacing the chain variables.
+        let before = state.commit();
+        let keys = state.verif_crs_commitment();
+
+        let chain = ChainVariables::new(7, VERIF_CRS.clone(), 5);
+        state.set_chain(chain.clone());
+        assert_ne!(state.commit(), before);
+        assert_eq!(state.commitment_opening().chain, chain.commit());
+        assert_eq!(state.chain_id(), 7);
+        // The verifier keys did not change.
+        assert_eq!(state.verif_crs_commitment(), keys);
+    }
+
+    #[test]
+    fn test_verify_commitment() {
+        let genesis = GenesisNote::empty();
+        let before = ValidatorState::default();
+        let after = ValidatorState::genesis(genesis);
+        assert!(before.verify_commitment(&before.commit()));
+        assert!(after.verify_commitment(&after.commit()));
+        assert!(!after.verify_commitment(&before.commit()));
+    }
+
+    #[test]
+    fn test_wire_block_to_elaborated() {
+        let state = ValidatorState::default();
+        let wire = |proofs, memos| WireBlock {
+            parent_state: state.commit(),
+            block: Block(vec![EspressoTransaction::Genesis(GenesisNote::empty())]),
+            proofs,
+            memos,
+        };
+
+        let block = wire(vec![EspressoTxnHelperProofs::Genesis], vec![None])
+            .to_elaborated(&state)
+            .unwrap();
+        assert_eq!(block.len(), 1);
+
+        // The number of proofs must match the number of transactions.
+        assert!(matches!(
+            wire(vec![], vec![None]).to_elaborated(&state),
+            Err(ValidationError::MissingNullifierProof {})
+        ));
+        // So must the number of memos.
+        assert!(matches!(
+            wire(vec![EspressoTxnHelperProofs::Genesis], vec![]).to_elaborated(&state),
+            Err(ValidationError::InconsistentHelperProofs)
+        ));
+        // The proofs must be of the same kind as their transaction.
+        assert!(matches!(
+            wire(vec![EspressoTxnHelperProofs::CAP(vec![])], vec![None]).to_elaborated(&state),
+            Err(ValidationError::InconsistentHelperProofs)
+        ));
+        // The block must be intended for the state.
+        assert!(matches!(
+            wire(vec![EspressoTxnHelperProofs::Genesis], vec![None])
+                .to_elaborated(&ValidatorState::genesis(GenesisNote::empty())),
+            Err(ValidationError::IncorrectParent)
+        ));
+    }
+
+    #[test]
+    fn test_arbitrary_block_shape() {
+        let mut rng = ChaChaRng::from_seed([0x42; 32]);
+        for _ in 0..10 {
+            let mut bytes = vec![0u8; 4096];
+            rng.fill_bytes(&mut bytes);
+            let block =
+                ElaboratedBlock::arbitrary(&mut arbitrary::Unstructured::new(&bytes)).unwrap();
+            assert!(block.block.0.len() <= 4);
+            assert_eq!(block.memos.len(), block.block.0.len());
+            // Only genesis transactions are generated, so there are no nullifier proofs to check.
+            assert!(block
+                .block
+                .0
+                .iter()
+                .all(|txn| matches!(txn, EspressoTransaction::Genesis(_))));
+            ElaboratedBlock::from_raw_parts(block.parent_state, block.block, block.proofs).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_block_iterators() {
+        let mut rng = ChaChaRng::from_seed([0x43; 32]);
+        let mut bytes = vec![0u8; 4096];
+        rng.fill_bytes(&mut bytes);
+        let block = ElaboratedBlock::arbitrary(&mut arbitrary::Unstructured::new(&bytes)).unwrap();
+
+        // Iterating by reference and by value yield the same transactions, in block order.
+        let by_ref = (&block).into_iter().collect::<Vec<_>>();
+        assert_eq!(by_ref.len(), block.len());
+        for (i, txn) in by_ref.iter().enumerate() {
+            assert_eq!(txn.txn, block.block.0[i]);
+            assert_eq!(txn.proofs, block.proofs[i]);
+            assert_eq!(txn.memos, block.memos[i]);
+        }
+        assert_eq!(block.into_iter().collect::<Vec<_>>(), by_ref);
+    }
+
+    #[test]
+    fn test_state_diff() {
+        let genesis = GenesisNote::empty();
+        let before = ValidatorState::default();
+        let after = ValidatorState::genesis(genesis);
+
+        let diff = after.state_diff(&before).unwrap();
+        assert!(diff.new_nullifiers.is_empty());
+        assert_eq!(diff.first_record_uid, 0);
+        assert_eq!(
+            diff.record_merkle_commitment,
+            after.record_merkle_commitment
+        );
+        assert_eq!(diff.commitment, after.commit());
+
+        // The diff from a state to itself is empty.
+        let diff = after.state_diff(&after).unwrap();
+        assert!(diff.new_nullifiers.is_empty());
+        assert_eq!(diff.first_record_uid, after.record_count());
+
+        // There is no diff from a state to an older one.
+        assert!(before.state_diff(&after).is_none());
+    }
+
+    #[test]
+    fn test_state_diff_nullifiers() {
+        let mut state = MultiXfrTestState::initialize(
+            [0x7bu8; 32],
+            2,
+            1,
+            (
+                MultiXfrRecordSpec {
+                    asset_def_ix: 1,
+                    owner_key_ix: 0,
+                    asset_amount: 1,
+                },
+                vec![],
+            ),
+        )
+        .unwrap();
+        let before = state.validator.clone();
+
+        // Apply a block containing a transfer, which spends nullifiers and creates records.
+        let tx = state
+            .generate_transactions(
+                vec![(TestTxSpec::OneInput { rec: 0, key: 1 }, false)],
+                TxnPrintInfo::new_no_time(0, 1),
+            )
+            .unwrap()
+            .remove(0);
+        let nullifiers = tx.transaction.nullifiers();
+        assert!(!nullifiers.is_empty());
+        let kixs = tx.keys_and_memos.into_iter().map(|(kix, _)| kix).collect();
+        let mut blk = state.validator.next_block();
+        state
+            .try_add_transaction(
+                &mut blk,
+                tx.transaction,
+                tx.index,
+                kixs,
+                TxnPrintInfo::new_no_time(0, 1),
+            )
+            .unwrap();
+        state
+            .validate_and_apply(
+                blk,
+                &state.next_view(),
+                0.0,
+                TxnPrintInfo::new_no_time(0, 1),
+            )
+            .unwrap();
+        let after = &state.validator;
+
+        let diff = after.state_diff(&before).unwrap();
+        assert_eq!(diff.new_nullifiers.len(), nullifiers.len());
+        assert_eq!(
+            diff.new_nullifiers.into_iter().collect::<HashSet<_>>(),
+            nullifiers.into_iter().collect::<HashSet<_>>()
+        );
+        assert_eq!(diff.first_record_uid, before.record_count());
+        assert!(after.record_count() > before.record_count());
+        assert_eq!(
+            diff.record_merkle_commitment,
+            after.record_merkle_commitment
+        );
+        assert_eq!(diff.commitment, after.commit());
+    }
+
+    /// A test state with a single record, and a block which spends it, built on that state.
+    fn transfer_block(seed: [u8; 32]) -> (MultiXfrTestState, ElaboratedBlock) {
+        let mut state = MultiXfrTestState::initialize(
+            seed,
+            2,
+            1,
+            (
+                MultiXfrRecordSpec {
+                    asset_def_ix: 1,
+                    owner_key_ix: 0,
+                    asset_amount: 1,
+                },
+                vec![],
+            ),
+        )
+        .unwrap();
+        let tx = state
+            .generate_transactions(
+                vec![(TestTxSpec::OneInput { rec: 0, key: 1 }, false)],
+                TxnPrintInfo::new_no_time(0, 1),
+            )
+            .unwrap()
+            .remove(0);
+        let kixs = tx.keys_and_memos.into_iter().map(|(kix, _)| kix).collect();
+        let mut blk = state.validator.next_block();
+        state
+            .try_add_transaction(
+                &mut blk,
+                tx.transaction,
+                tx.index,
+                kixs,
+                TxnPrintInfo::new_no_time(0, 1),
+            )
+            .unwrap();
+        (state, blk)
+    }
+
+    #[test]
+    fn test_rewind() {
+        let (mut state, blk) = transfer_block([0x7cu8; 32]);
+        let before = state.validator.clone();
+        let now = state.next_view();
+        state
+            .validate_and_apply(blk.clone(), &now, 0.0, TxnPrintInfo::new_no_time(0, 1))
+            .unwrap();
+        let after = state.validator.commit();
+        assert_ne!(before.commit(), after);
+        let history = vec![(blk, now)];
+
+        // Replaying the history from the earlier state reaches the later one.
+        let rewound = before.rewind(&after, &history).unwrap();
+        assert_eq!(rewound.commit(), after);
+        // The earlier state is reached without applying any blocks.
+        let rewound = before.rewind(&before.commit(), &history).unwrap();
+        assert_eq!(rewound.commit(), before.commit());
+
+        // A target which is not in the history cannot be reached.
+        assert!(matches!(
+            before.rewind(&after, &[]),
+            Err(RewindError::Unreachable { target }) if target == after
+        ));
+        // Blocks which do not apply to the starting state are reported.
+        assert!(matches!(
+            state.validator.rewind(&before.commit(), &history),
+            Err(RewindError::InvalidBlock {
+                index: 0,
+                source: ValidationError::IncorrectParent,
+            })
+        ));
+    }
+
+    #[test]
+    fn test_validate_block_dry_run() {
+        let (state, blk) = transfer_block([0x7du8; 32]);
+        let validator = &state.validator;
+        let comm = validator.commit();
+        let now = state.next_view();
+        let txn = blk.block.0[0].clone();
+        let proofs = blk.proofs[0].clone();
+
+        // Each transaction gets its own result, and later transactions are checked against
+        // earlier ones in the same block.
+        let results = validator.validate_block_dry_run(
+            &now,
+            comm,
+            Block(vec![txn.clone(), txn.clone()]),
+            vec![proofs.clone(), proofs.clone()],
+        );
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(ValidationError::NullifierAlreadyExists { .. })
+        ));
+        // A transaction without proofs is reported on its own.
+        let results = validator.validate_block_dry_run(
+            &now,
+            comm,
+            Block(vec![txn.clone(), txn.clone()]),
+            vec![proofs.clone()],
+        );
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(ValidationError::MissingNullifierProof {})
+        ));
+        // If the block is not intended for this state, every transaction is rejected.
+        let results = validator.validate_block_dry_run(
+            &now,
+            ValidatorState::default().commit(),
+            Block(vec![txn.clone(), txn]),
+            vec![proofs.clone(), proofs],
+        );
+        assert_eq!(results.len(), 2);
+        assert!(results
+            .iter()
+            .all(|res| matches!(res, Err(ValidationError::IncorrectParent))));
+
+        // None of this changes the state.
+        assert_eq!(validator.commit(), comm);
+    }
+
+    #[test]
+    fn test_apply_block_unchecked() {
+        let (state, blk) = transfer_block([0x7eu8; 32]);
+        let now = state.next_view();
+
+        let mut checked = state.validator.clone();
+        let checked_outputs = checked
+            .validate_and_apply(
+                &now,
+                blk.parent_state,
+                blk.block.clone(),
+                blk.proofs.clone(),
+            )
+            .unwrap();
+        let mut unchecked = state.validator.clone();
+        let unchecked_outputs = unchecked
+            .apply_block_unchecked(
+                &now,
+                blk.parent_state,
+                blk.block.clone(),
+                blk.proofs.clone(),
+            )
+            .unwrap();
+
+        // Skipping proof verification does not change the result of applying a valid block.
+        assert_eq!(unchecked.commit(), checked.commit());
+        assert_eq!(unchecked_outputs.uids, checked_outputs.uids);
+
+        // The checks other than proof verification still apply.
+        let mut unchecked = state.validator.clone();
+        assert!(matches!(
+            unchecked.apply_block_unchecked(
+                &now,
+                ValidatorState::default().commit(),
+                blk.block.clone(),
+                blk.proofs.clone(),
+            ),
+            Err(ValidationError::IncorrectParent)
+        ));
+        assert!(matches!(
+            unchecked.apply_block_unchecked(
+                &now,
+                blk.parent_state,
+                Block(vec![blk.block.0[0].clone(), blk.block.0[0].clone()]),
+                vec![blk.proofs[0].clone(), blk.proofs[0].clone()],
+            ),
+            Err(ValidationError::NullifierAlreadyExists { .. })
+        ));
+        assert_eq!(unchecked.commit(), state.validator.commit());
+    }
+
+    #[test]
+    fn test_merge_blocks() {
+        let (_, blk) = transfer_block([0x7fu8; 32]);
+        let parent = blk.parent_state;
+        let genesis = ElaboratedBlock::from_transactions(
+            parent,
+            vec![ElaboratedTransaction {
+                txn: EspressoTransaction::Genesis(GenesisNote::empty()),
+                proofs: EspressoTxnHelperProofs::Genesis,
+                memos: None,
+                memo: None,
+            }],
+        );
+
+        // Merging with an empty block changes nothing.
+        let merged = blk.clone().merge(ElaboratedBlock::new(parent)).unwrap();
+        assert_eq!(merged.commit(), blk.commit());
+        let merged = ElaboratedBlock::new(parent).merge(blk.clone()).unwrap();
+        assert_eq!(merged.commit(), blk.commit());
+
+        // The transactions of the second block follow those of the first.
+        let merged = genesis.clone().merge(blk.clone()).unwrap();
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged.block.0[0], genesis.block.0[0]);
+        assert_eq!(merged.block.0[1], blk.block.0[0]);
+        assert_eq!(merged.proofs[1], blk.proofs[0]);
+        assert_eq!(merged.memos[1], blk.memos[0]);
+
+        // Blocks built on different states cannot be merged.
+        assert!(matches!(
+            blk.clone()
+                .merge(ElaboratedBlock::new(ValidatorState::default().commit())),
+            Err(ValidationError::IncorrectParent)
+        ));
+        // Neither can blocks which spend the same nullifier.
+        assert!(matches!(
+            blk.clone().merge(blk),
+            Err(ValidationError::ConflictingNullifiers {})
+        ));
+    }
+
+    #[test]
+    fn test_filter_transactions() {
+        let (state, blk) = transfer_block([0x80u8; 32]);
+        let now = state.next_view();
+        let txn = (&blk).into_iter().next().unwrap();
+
+        // A transaction which conflicts with an earlier one is filtered out, and the rest are
+        // kept in order.
+        let double_spend =
+            ElaboratedBlock::from_transactions(blk.parent_state, vec![txn.clone(), txn.clone()]);
+        let (valid, rejected) = double_spend.filter_transactions(&state.validator, &now);
+        assert_eq!(valid.parent_state, blk.parent_state);
+        assert_eq!(valid.commit(), blk.commit());
+        assert_eq!(rejected.len(), 1);
+        assert!(matches!(
+            rejected[0],
+            (1, ValidationError::NullifierAlreadyExists { .. })
+        ));
+
+        // If the block is not intended for the state, every transaction is rejected.
+        let wrong_parent = ElaboratedBlock::from_transactions(
+            ValidatorState::default().commit(),
+            vec![txn.clone(), txn],
+        );
+        let (valid, rejected) = wrong_parent.filter_transactions(&state.validator, &now);
+        assert!(valid.is_empty());
+        assert_eq!(
+            rejected.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+        assert!(rejected
+            .iter()
+            .all(|(_, err)| matches!(err, ValidationError::IncorrectParent)));
+    }
+
+    #[test]
+    fn test_block_at_capacity() {
+        let (state, blk) = transfer_block([0x81u8; 32]);
+        let validator = &state.validator;
+        let empty = ElaboratedBlock::new(blk.parent_state);
+
+        // A block is full once it reaches the transaction limit.
+        assert!(!validator.block_at_capacity(&empty, 1));
+        assert!(validator.block_at_capacity(&blk, 1));
+        assert!(!validator.block_at_capacity(&blk, 2));
+        assert!(validator.block_at_capacity(&empty, 0));
+
+        // It is also full if its outputs would fill up the record Merkle tree.
+        let mut nearly_full = validator.clone();
+        nearly_full.record_merkle_commitment.num_leaves =
+            3u64.pow(nearly_full.record_merkle_commitment.height as u32) - 1;
+        assert!(!nearly_full.block_at_capacity(&empty, 2));
+        assert!(nearly_full.block_at_capacity(&blk, 2));
+    }
+
+    #[test]
+    fn test_check_nullifier_status() {
+        let (mut state, blk) = transfer_block([0x82u8; 32]);
+        let nullifier = blk.block.0[0].input_nullifiers()[0];
+        let proof = match &blk.proofs[0] {
+            EspressoTxnHelperProofs::CAP(proofs) => proofs[0].clone(),
+            _ => panic!("expected CAP proofs for a transfer"),
+        };
+        assert_eq!(
+            state.validator.check_nullifier_status(nullifier, &proof),
+            NullifierStatus::Unspent
+        );
+
+        state
+            .validate_and_apply(
+                blk,
+                &state.next_view(),
+                0.0,
+                TxnPrintInfo::new_no_time(0, 1),
+            )
+            .unwrap();
+        // The old proof of non-membership no longer shows the nullifier is unspent...
+        assert_eq!(
+            state.validator.check_nullifier_status(nullifier, &proof),
+            NullifierStatus::Spent
+        );
+        // ...and neither does a proof of membership in the new set.
+        let (spent, proof) = state.nullifiers.contains(nullifier).unwrap();
+        assert!(spent);
+        assert_eq!(
+            state.validator.check_nullifier_status(nullifier, &proof),
+            NullifierStatus::Spent
+        );
+
+        // A proof relative to some other nullifier set is not valid.
+        let mut rng = ChaChaRng::from_seed([0x82u8; 32]);
+        let mut other = SetMerkleTree::default();
+        other.insert(Nullifier::random_for_test(&mut rng));
+        let (_, proof) = other.contains(nullifier).unwrap();
+        assert_eq!(
+            state.validator.check_nullifier_status(nullifier, &proof),
+            NullifierStatus::InvalidProof
+        );
+    }
+
+    #[test]
+    fn test_block_into_transactions() {
+        let (_, blk) = transfer_block([0x83u8; 32]);
+
+        // Splitting a block into its transactions and putting them back together is lossless.
+        let txns = Vec::<ElaboratedTransaction>::try_from(blk.clone()).unwrap();
+        assert_eq!(txns.len(), 1);
+        assert_eq!(txns[0].txn, blk.block.0[0]);
+        assert_eq!(txns[0].proofs, blk.proofs[0]);
+        assert_eq!(txns[0].memos, blk.memos[0]);
+        let rebuilt = ElaboratedBlock::try_from((blk.parent_state, txns)).unwrap();
+        assert_eq!(rebuilt.commit(), blk.commit());
+
+        // Every transaction must have its own proofs and memos.
+        let mut missing_memos = blk.clone();
+        missing_memos.memos.clear();
+        assert!(matches!(
+            Vec::<ElaboratedTransaction>::try_from(missing_memos),
+            Err(ValidationError::InconsistentHelperProofs)
+        ));
+        let mut extra_proofs = blk.clone();
+        extra_proofs.proofs.push(blk.proofs[0].clone());
+        assert!(matches!(
+            Vec::<ElaboratedTransaction>::try_from(extra_proofs),
+            Err(ValidationError::InconsistentHelperProofs)
+        ));
+    }
+
+    #[test]
+    fn test_validate_transaction() {
+        let (mut state, blk) = transfer_block([0x84u8; 32]);
+        let txn = (&blk).into_iter().next().unwrap();
+        let now = state.next_view();
+        assert!(validate_transaction(&state.validator, &now, &txn).is_ok());
+
+        // The cheap checks reject a transaction before any proofs are verified.
+        let mut wrong_proofs = txn.clone();
+        wrong_proofs.proofs = EspressoTxnHelperProofs::Genesis;
+        assert!(matches!(
+            validate_transaction(&state.validator, &now, &wrong_proofs),
+            Err(ValidationError::InconsistentHelperProofs)
+        ));
+
+        state
+            .validate_and_apply(blk, &now, 0.0, TxnPrintInfo::new_no_time(0, 1))
+            .unwrap();
+        assert!(matches!(
+            validate_transaction(&state.validator, &state.next_view(), &txn),
+            Err(ValidationError::NullifierAlreadyExists { .. })
+        ));
+        assert!(matches!(
+            validate_transaction(&state.validator, &ConsensusTime::genesis(), &txn),
+            Err(ValidationError::InvalidTime)
+        ));
+    }
+
+    #[test]
+    fn test_default_key_sets_compatible() {
+        assert_eq!(check_key_sets_compatible(&PROVER_CRS, &VERIF_CRS), Ok(()));
+    }
+
+    #[test]
+    fn test_state_commitment_display() {
+        // The Display impl generated by `tagged_blob` matches the serde representation.
+        let comm = ValidatorState::default().commit();
+        assert!(comm.to_string().starts_with("STATE~"));
+        assert_eq!(
+            serde_json::to_string(&comm).unwrap(),
+            format!("\"{}\"", comm)
+        );
+    }
+
+    #[test]
+    fn test_state_commitment_hex_round_trip() {
+        let comm = ValidatorState::default().commit();
+        let hex = comm.to_hex();
+        assert_eq!(LedgerStateCommitment::from_hex(&hex).unwrap(), comm);
+        assert_eq!(
+            LedgerStateCommitment::from_hex(&format!("0x{}", hex)).unwrap(),
+            comm
+        );
+        assert!(LedgerStateCommitment::from_hex("not hex").is_err());
+        assert!(LedgerStateCommitment::from_hex(&hex[2..]).is_err());
+    }
 }
@@ -6,25 +6,42 @@
 //! Give faucet-shower a master mnemonic for a funded keystore and a number N and it will generate N
 //! new keystores, transfer some tokens from the master keystore to each new keystore, and print the
 //! mnemonics and public keys of the newly funded keystores.
+use async_std::future::timeout;
+use async_std::sync::Mutex;
+use async_std::task::{sleep, spawn};
 use clap::Parser;
 use espresso_client::{
     hd::{KeyTree, Mnemonic},
     ledger_state::TransactionStatus,
     loader::{CreateLoader, MnemonicPasswordLogin},
     network::NetworkBackend,
-    EspressoKeystore, EspressoKeystoreError,
+    EspressoKeystore, EspressoKeystoreError, KeystoreError,
 };
 use espresso_core::universal_params::UNIVERSAL_PARAM;
+use futures::future::join;
 use futures::stream::{iter, StreamExt};
-use jf_cap::structs::AssetCode;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use jf_cap::{keys::UserPubKey, structs::AssetCode};
 use primitive_types::U256;
 use rand_chacha::{
     rand_core::{RngCore, SeedableRng},
     ChaChaRng,
 };
+use serde::{Deserialize, Serialize};
+use std::cmp::min;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::iter::repeat;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
 use std::time::Duration;
+use surf_disco::{error::ClientError, Client};
 use tempdir::TempDir;
 use tide_disco::Url;
 
@@ -34,17 +51,76 @@ pub struct Options {
     #[arg(short, long, env = "ESPRESSO_FAUCET_WALLET_MNEMONIC")]
     pub master_mnemonic: Mnemonic,
 
-    /// number of new keystores to generate
-    #[arg(short, long, default_value = "10")]
-    pub num_keystores: usize,
+    /// TOML file with the number of keystores and the records to create in each
+    ///
+    /// See faucet-shower.example.toml for the format. Options given on the command line take
+    /// precedence over the config file.
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// number of new keystores to generate [default: 10]
+    #[arg(short, long)]
+    pub num_keystores: Option<usize>,
+
+    /// number of records to create in each new keystore [default: 1]
+    ///
+    /// This overrides the number of records for every keystore, including any per-keystore
+    /// settings in the config file.
+    #[arg(short, long)]
+    pub num_records: Option<u64>,
+
+    /// size of each record to create in the new keystores [default: 1000000]
+    ///
+    /// This overrides the record size for every keystore, including any per-keystore settings in
+    /// the config file.
+    #[arg(short, long)]
+    pub record_size: Option<u64>,
+
+    /// maximum number of transfers to have in flight at once
+    #[arg(long, default_value = "1")]
+    pub concurrency: NonZeroUsize,
+
+    /// number of times to retry a transfer which fails because of a transient error
+    #[arg(long, default_value = "3")]
+    pub max_retries: usize,
+
+    /// how long to wait for each transfer to be confirmed, in seconds
+    #[arg(long, default_value = "60")]
+    pub timeout_secs: u64,
+
+    /// how long to wait for the parent keystore to finish scanning the ledger, in seconds
+    #[arg(long, default_value = "300")]
+    pub wait_scan_timeout_secs: u64,
+
+    /// asset to distribute, as a tagged-blob encoded asset code (default: the native asset)
+    #[arg(long)]
+    pub asset_code: Option<AssetCode>,
 
-    /// number of records to create in each new keystore
-    #[arg(short, long, default_value = "1")]
-    pub num_records: u64,
+    /// fee to pay for each transfer, in units of the native asset
+    #[arg(long, default_value = "0")]
+    pub fee: u64,
 
-    /// size of each record to create in the new keystores
-    #[arg(short, long, default_value = "1000000")]
-    pub record_size: u64,
+    /// print the transfers which would be made, without making them
+    ///
+    /// The child keystores are still created, and the output is the same as for a live run in which
+    /// every transfer succeeds, except that no manifest is written (see `--output-json`).
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// file to write a JSON manifest of the generated keystores to
+    ///
+    /// The manifest is a JSON array with one element for each keystore, containing its mnemonic,
+    /// public key, and the amount successfully transferred to it. It is written once all transfers
+    /// have completed. No manifest is written in `--dry-run` mode, since nothing is transferred.
+    #[arg(long)]
+    pub output_json: Option<PathBuf>,
+
+    /// do not show a progress bar
+    ///
+    /// By default, a progress bar showing completed and in-flight transfers is drawn on stderr if
+    /// stderr is a terminal.
+    #[arg(long)]
+    pub no_progress: bool,
 
     /// URL for the Ethereum Query Service.
     #[arg(
@@ -53,17 +129,202 @@ pub struct Options {
         default_value = "http://localhost:50087"
     )]
     pub esqs_url: Url,
+
+    /// how often to check that the EsQS is still reachable, in seconds (at least 1)
+    #[arg(long, default_value = "60", value_parser = clap::value_parser!(u64).range(1..))]
+    pub esqs_health_check_interval_secs: u64,
+}
+
+/// Connectivity to the EsQS, as observed by periodic health checks.
+struct EsqsHealth {
+    client: Client<ClientError>,
+    consecutive_failures: AtomicUsize,
+    progress: ProgressBar,
+}
+
+impl EsqsHealth {
+    /// The number of consecutive failed health checks after which we stop transferring.
+    const MAX_FAILURES: usize = 3;
+    /// The longest we will wait between health checks while transfers are paused.
+    const MAX_BACKOFF: Duration = Duration::from_secs(64);
+
+    /// Warnings are written through `progress`, so that they do not get mixed up with the bar.
+    fn new(url: Url, progress: ProgressBar) -> Self {
+        Self {
+            client: Client::new(url),
+            consecutive_failures: AtomicUsize::new(0),
+            progress,
+        }
+    }
+
+    /// Check if the EsQS is reachable, updating the count of consecutive failures.
+    async fn ping(&self) -> bool {
+        match self
+            .client
+            .get::<u64>("status/latest_block_id")
+            .send()
+            .await
+        {
+            Ok(_) => {
+                self.consecutive_failures.store(0, Ordering::SeqCst);
+                true
+            }
+            Err(err) => {
+                let failures = self.consecutive_failures.fetch_add(1, Ordering::SeqCst) + 1;
+                self.progress.suspend(|| {
+                    eprintln!(
+                        "Warning: EsQS is unreachable ({} consecutive failures): {}",
+                        failures, err
+                    )
+                });
+                false
+            }
+        }
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.consecutive_failures.load(Ordering::SeqCst) < Self::MAX_FAILURES
+    }
+
+    /// Block until the EsQS is healthy, re-checking with exponential back-off.
+    async fn wait_until_healthy(&self) {
+        let mut backoff = Duration::from_secs(1);
+        while !self.is_healthy() {
+            self.progress.suspend(|| {
+                eprintln!(
+                    "Pausing transfers until the EsQS is reachable, retrying in {:?}",
+                    backoff
+                )
+            });
+            sleep(backoff).await;
+            backoff = min(backoff * 2, Self::MAX_BACKOFF);
+            self.ping().await;
+        }
+    }
+}
+
+/// The contents of a `--config` file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    num_keystores: Option<usize>,
+    num_records: Option<u64>,
+    record_size: Option<u64>,
+    #[serde(default)]
+    keystores: Vec<KeystoreConfig>,
+}
+
+/// Settings for an individual keystore in a `--config` file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeystoreConfig {
+    index: usize,
+    num_records: Option<u64>,
+    record_size: Option<u64>,
+}
+
+impl Config {
+    fn load(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|err| err.to_string())?;
+        toml::from_slice(&bytes).map_err(|err| err.to_string())
+    }
+
+    /// The records to transfer to each keystore.
+    ///
+    /// Options from the command line take precedence over the config file, and per-keystore
+    /// settings take precedence over the defaults in the config file.
+    fn allocations(&self, opt: &Options) -> Result<Vec<Allocation>, String> {
+        let num_keystores = opt.num_keystores.or(self.num_keystores).unwrap_or(10);
+        let default = Allocation {
+            num_records: opt.num_records.or(self.num_records).unwrap_or(1),
+            record_size: opt.record_size.or(self.record_size).unwrap_or(1000000),
+        };
+        let mut allocations = vec![default; num_keystores];
+        for keystore in &self.keystores {
+            let allocation = allocations.get_mut(keystore.index).ok_or_else(|| {
+                format!(
+                    "config for keystore {} is out of range, there are only {} keystores",
+                    keystore.index, num_keystores
+                )
+            })?;
+            if opt.num_records.is_none() {
+                if let Some(num_records) = keystore.num_records {
+                    allocation.num_records = num_records;
+                }
+            }
+            if opt.record_size.is_none() {
+                if let Some(record_size) = keystore.record_size {
+                    allocation.record_size = record_size;
+                }
+            }
+        }
+        Ok(allocations)
+    }
+}
+
+/// The records to transfer to one child keystore.
+#[derive(Clone, Copy, Debug)]
+struct Allocation {
+    num_records: u64,
+    record_size: u64,
+}
+
+impl Allocation {
+    fn total(&self) -> U256 {
+        U256::from(self.record_size) * self.num_records
+    }
+}
+
+/// An entry in the manifest written by `--output-json`.
+#[derive(Debug, Serialize)]
+struct ManifestEntry {
+    mnemonic: String,
+    public_key: String,
+    balance_transferred: u128,
 }
 
+/// Write `manifest` to `path` as JSON.
+///
+/// The manifest is first written to a temporary file which is then renamed to `path`, so that a
+/// partially written manifest is never mistaken for a complete one.
+fn write_manifest(path: &Path, manifest: &[ManifestEntry]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(manifest)?)?;
+    fs::rename(&tmp, path)
+}
+
+/// Write the `--output-json` manifest, if requested.
+fn write_output_json<K, A: Display>(
+    opt: &Options,
+    children: &[(K, Mnemonic, A)],
+    transferred: &[u128],
+) {
+    if let Some(path) = &opt.output_json {
+        let manifest = children
+            .iter()
+            .zip(transferred)
+            .map(|((_, mnemonic, key), transferred)| ManifestEntry {
+                mnemonic: mnemonic.to_string(),
+                public_key: key.to_string(),
+                balance_transferred: *transferred,
+            })
+            .collect::<Vec<_>>();
+        if let Err(err) = write_manifest(path, &manifest) {
+            eprintln!("Failed to write manifest to {}: {}", path.display(), err);
+            exit(1);
+        }
+    }
+}
+
+type Keystore = EspressoKeystore<'static, NetworkBackend<'static>, MnemonicPasswordLogin>;
+
 async fn create_keystore(
     opt: &Options,
     rng: &mut ChaChaRng,
     mnemonic: Mnemonic,
     dir: PathBuf,
-) -> Result<
-    EspressoKeystore<'static, NetworkBackend<'static>, MnemonicPasswordLogin>,
-    EspressoKeystoreError,
-> {
+) -> Result<Keystore, EspressoKeystoreError> {
     // We are never going to re-open this keystore once it's created, so we don't really need a
     // password. Just make it random bytes.
     let mut password = [0; 32];
@@ -79,12 +340,157 @@ async fn create_keystore(
     EspressoKeystore::new(backend, &mut loader).await
 }
 
+/// Create a progress bar for the transfers.
+///
+/// The bar starts out hidden, so that it can be shared with tasks which are started before the
+/// transfers, like the EsQS health checks. It is shown by [start_transfer_progress]. Anything
+/// written to stderr while the bar is visible should go through [ProgressBar::suspend], so that it
+/// does not get mixed up with the bar.
+fn transfer_progress() -> ProgressBar {
+    let progress = ProgressBar::hidden();
+    progress.set_style(
+        ProgressStyle::with_template(
+            "[{elapsed_precise}] {wide_bar} {pos}/{len} transfers confirmed, {msg} (ETA {eta})",
+        )
+        .unwrap(),
+    );
+    progress
+}
+
+/// Start showing `progress` for `len` transfers.
+///
+/// The bar stays hidden if `--no-progress` was given or stderr is not a terminal.
+fn start_transfer_progress(opt: &Options, progress: &ProgressBar, len: usize) {
+    progress.set_length(len as u64);
+    progress.reset();
+    if !opt.no_progress {
+        progress.set_draw_target(ProgressDrawTarget::stderr());
+    }
+}
+
+/// Whether a failed transfer might succeed if we try again.
+///
+/// Network errors are reported by the backend as [KeystoreError::Failed]. Any other error, such as
+/// an insufficient balance, will not go away by itself.
+fn is_transient(err: &EspressoKeystoreError) -> bool {
+    matches!(err, KeystoreError::Failed { .. })
+}
+
+/// Why a transfer to a child keystore did not complete.
+#[derive(Debug)]
+enum TransferError {
+    /// The transfer could not be built or submitted, or its status could not be checked.
+    Keystore(EspressoKeystoreError),
+    /// The transfer was submitted, but it was not confirmed in time.
+    Timeout(Duration),
+    /// The transfer was submitted, but it did not succeed.
+    Failed(TransactionStatus),
+}
+
+impl Display for TransferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Keystore(err) => write!(f, "{}", err),
+            Self::Timeout(duration) => write!(f, "timed out after {:?}", duration),
+            Self::Failed(status) => write!(f, "transfer did not complete successfully: {}", status),
+        }
+    }
+}
+
+impl TransferError {
+    /// Whether the transfer might succeed if we build and submit it again.
+    ///
+    /// A transfer which was rejected (for example, because it was built against a ledger state
+    /// which was too old by the time it was validated) is worth retrying. A transfer which timed
+    /// out is not: it was submitted and may still be confirmed, so retrying it could fund the child
+    /// twice. It is reported as unfunded at the end instead.
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Keystore(err) => is_transient(err),
+            Self::Failed(_) => true,
+            Self::Timeout(_) => false,
+        }
+    }
+}
+
+/// How often to check whether a submitted transfer has been confirmed.
+const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The longest we will wait before retrying a failed transfer.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(64);
+
+/// Transfer a record of `amount` to `receiver` and wait for the transfer to be confirmed.
+///
+/// Building a transfer requires exclusive access to the parent keystore, so it is locked while the
+/// transfer is built and submitted. After that, the keystore is only locked briefly each time we
+/// check the status of the transfer, so that other transfers can be built while this one is waiting
+/// to be confirmed.
+async fn transfer_and_confirm(
+    parent: &Mutex<Keystore>,
+    opt: &Options,
+    asset: &AssetCode,
+    receiver: &UserPubKey,
+    amount: u64,
+) -> Result<(), TransferError> {
+    let receipt = parent
+        .lock()
+        .await
+        .transfer(None, asset, &[(receiver.clone(), amount)], opt.fee)
+        .await
+        .map_err(TransferError::Keystore)?;
+    let confirmation = async {
+        loop {
+            let status = parent
+                .lock()
+                .await
+                .transaction_status(&receipt)
+                .await
+                .map_err(TransferError::Keystore)?;
+            if status.succeeded() {
+                return Ok(());
+            } else if status.is_final() {
+                return Err(TransferError::Failed(status));
+            }
+            sleep(STATUS_POLL_INTERVAL).await;
+        }
+    };
+    let timeout_duration = Duration::from_secs(opt.timeout_secs);
+    timeout(timeout_duration, confirmation)
+        .await
+        .unwrap_or(Err(TransferError::Timeout(timeout_duration)))
+}
+
 #[async_std::main]
 async fn main() {
     let opt = Options::parse();
+    let config = match &opt.config {
+        Some(path) => Config::load(path).unwrap_or_else(|err| {
+            eprintln!("Failed to read config file {}: {}", path.display(), err);
+            exit(1);
+        }),
+        None => Config::default(),
+    };
+    let allocations = config.allocations(&opt).unwrap_or_else(|err| {
+        eprintln!("Invalid config: {}", err);
+        exit(1);
+    });
     let mut rng = ChaChaRng::from_entropy();
     let dir = TempDir::new("faucet-shower").unwrap();
 
+    // Periodically check that we can still reach the EsQS for as long as we are running.
+    let progress = transfer_progress();
+    let health = Arc::new(EsqsHealth::new(opt.esqs_url.clone(), progress.clone()));
+    spawn({
+        let health = health.clone();
+        let interval = Duration::from_secs(opt.esqs_health_check_interval_secs);
+        async move {
+            loop {
+                sleep(interval).await;
+                health.ping().await;
+            }
+        }
+    });
+
     // Create the parent keystore.
     let parent_dir = [dir.path(), Path::new("parent")].iter().collect();
     let mut parent = create_keystore(&opt, &mut rng, opt.master_mnemonic.clone(), parent_dir)
@@ -98,8 +504,18 @@ async fn main() {
         .await
         .unwrap();
 
-    // While the ledger scan is going, create the child keystores.
-    let children = iter(0..opt.num_keystores)
+    // Create the child keystores while the parent's ledger scan is running. The scan and the
+    // child-creation stream are independent, so we drive them concurrently. Note that we cannot
+    // start transferring until the scan has completed: before then, the parent keystore may not
+    // have discovered the record created by the faucet event, and any transfer would fail with an
+    // insufficient balance. `join` only resolves once both futures are done, so everything after
+    // this point is guaranteed to see the completed scan.
+    let scan_timeout = Duration::from_secs(opt.wait_scan_timeout_secs);
+    let scan = timeout(
+        scan_timeout,
+        parent.await_sending_key_scan(&parent_key.address()),
+    );
+    let children = iter(0..allocations.len())
         .then(|i| {
             let mut rng = ChaChaRng::from_rng(&mut rng).unwrap();
             let dir = &dir;
@@ -119,71 +535,187 @@ async fn main() {
                 (keystore, mnemonic, key)
             }
         })
-        .collect::<Vec<_>>()
-        .await;
+        .collect::<Vec<_>>();
+    let (scan_result, children) = join(scan, children).await;
+    match scan_result {
+        Ok(result) => result.unwrap(),
+        Err(_) => {
+            eprintln!(
+                "Timed out after {:?} waiting for the parent keystore to scan the ledger",
+                scan_timeout
+            );
+            exit(1);
+        }
+    }
 
-    // Once we have all the keystores, we need to wait for the ledger scan so that the parent keystore
-    // can discover a record to transfer from.
-    parent
-        .await_sending_key_scan(&parent_key.address())
-        .await
-        .unwrap();
-    let balance = parent.balance(&AssetCode::native()).await;
-    let total_per_keystore = U256::from(opt.record_size) * opt.num_records;
-    if balance < total_per_keystore * opt.num_keystores {
-        eprintln!(
-            "Insufficient balance for transferring {} units to {} keystores: {}",
-            total_per_keystore, opt.num_keystores, balance
-        );
-        exit(1);
+    let asset = opt.asset_code.unwrap_or_else(AssetCode::native);
+    let balance = parent.balance(&asset).await;
+    let total = allocations
+        .iter()
+        .fold(U256::zero(), |total, allocation| total + allocation.total());
+    // Fees are paid in the native asset, whichever asset we are distributing.
+    let total_fees = allocations.iter().fold(U256::zero(), |total, allocation| {
+        total + U256::from(opt.fee) * allocation.num_records
+    });
+    if asset == AssetCode::native() {
+        if balance < total + total_fees {
+            eprintln!(
+                "Insufficient balance for transferring {} units to {} keystores with {} in fees: {}",
+                total,
+                allocations.len(),
+                total_fees,
+                balance
+            );
+            exit(1);
+        }
+    } else {
+        if balance < total {
+            eprintln!(
+                "Insufficient balance for transferring {} units to {} keystores: {}",
+                total,
+                allocations.len(),
+                balance
+            );
+            exit(1);
+        }
+        let native_balance = parent.balance(&AssetCode::native()).await;
+        if native_balance < total_fees {
+            eprintln!(
+                "Insufficient native balance for paying {} in fees: {}",
+                total_fees, native_balance
+            );
+            exit(1);
+        }
     }
 
     // Print out the generated child mnemonics and keys _before_ we start doing any transfers. If we
     // panic or get killed for any reason after we have transferred, it is crucial that we have
     // already reported all of the mnemonics needed to recover the funds.
     println!(
-        "Transferring {} units each to the following keystores:",
-        total_per_keystore
+        "Transferring {} units in total to the following keystores:",
+        total
     );
     for (_, mnemonic, key) in &children {
         println!("{} {}", mnemonic, key);
     }
 
-    // Do the transfers.
-    for (_, _, key) in &children {
-        for _ in 0..opt.num_records {
-            match parent
-                .transfer(
-                    None,
-                    &AssetCode::native(),
-                    &[(key.clone(), opt.record_size)],
-                    0,
-                )
-                .await
-            {
-                Ok(receipt) => match parent.await_transaction(&receipt).await {
-                    Ok(TransactionStatus::Retired) => {
-                        println!("Transferred {} units to {}", opt.record_size, key)
+    // The amount successfully transferred to each child.
+    let mut transferred = vec![0u128; children.len()];
+
+    if opt.dry_run {
+        eprintln!("Dry run: not submitting any transfers");
+        for (((_, _, key), allocation), transferred) in
+            children.iter().zip(&allocations).zip(&mut transferred)
+        {
+            for _ in 0..allocation.num_records {
+                println!("Transferred {} units to {}", allocation.record_size, key);
+                *transferred += allocation.record_size as u128;
+            }
+        }
+        if opt.output_json.is_some() {
+            eprintln!("Dry run: not writing a manifest");
+        }
+        return;
+    }
+
+    // Do the transfers, keeping up to `concurrency` of them in flight at once. As soon as one
+    // transfer is confirmed (or fails), the next one is started, so a slow confirmation does not hold
+    // up the others. Note that transfers can only be in flight at the same time if the parent
+    // keystore has enough separate records to fund them; otherwise, the later transfers will fail
+    // until the change from the earlier ones is returned.
+    let transfers = children
+        .iter()
+        .zip(&allocations)
+        .enumerate()
+        .flat_map(|(i, ((_, _, key), allocation))| {
+            repeat((i, key, allocation.record_size)).take(allocation.num_records as usize)
+        })
+        .collect::<Vec<_>>();
+    start_transfer_progress(&opt, &progress, transfers.len());
+    progress.set_message("0 in flight");
+    let parent = Mutex::new(parent);
+    let in_flight = AtomicUsize::new(0);
+    let mut results = iter(transfers)
+        .map(|(i, key, record_size)| {
+            let parent = &parent;
+            let opt = &opt;
+            let asset = &asset;
+            let health = &health;
+            let progress = &progress;
+            let in_flight = &in_flight;
+            async move {
+                let n = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                progress.set_message(format!("{} in flight", n));
+                let mut retries = 0;
+                let mut backoff = Duration::from_secs(1);
+                let result = loop {
+                    health.wait_until_healthy().await;
+                    match transfer_and_confirm(parent, opt, asset, key, record_size).await {
+                        Err(err) if err.is_transient() && retries < opt.max_retries => {
+                            progress.suspend(|| {
+                                eprintln!(
+                                    "Failed to transfer to {}, retrying in {:?}: {}",
+                                    key, backoff, err
+                                )
+                            });
+                            sleep(backoff).await;
+                            backoff = min(backoff * 2, MAX_RETRY_BACKOFF);
+                            retries += 1;
+                        }
+                        result => break result,
                     }
-                    Ok(status) => eprintln!(
-                        "Transfer to {} did not complete successfully: {}",
-                        key, status
-                    ),
-                    Err(err) => eprintln!("Error while waiting for transfer to {}: {}", key, err),
-                },
-                Err(err) => eprintln!("Failed to transfer to {}: {}", key, err),
+                };
+                let n = in_flight.fetch_sub(1, Ordering::SeqCst) - 1;
+                progress.set_message(format!("{} in flight", n));
+                (i, key, record_size, result)
+            }
+        })
+        .buffer_unordered(opt.concurrency.get());
+    while let Some((i, key, record_size, result)) = results.next().await {
+        progress.inc(1);
+        match result {
+            Ok(()) => {
+                progress.suspend(|| println!("Transferred {} units to {}", record_size, key));
+                transferred[i] += record_size as u128;
             }
+            Err(err) => progress.suspend(|| eprintln!("Failed to transfer to {}: {}", key, err)),
         }
     }
+    progress.finish_and_clear();
 
-    // Wait for the children to report the new balances.
-    for (keystore, _, key) in &children {
-        while keystore.balance(&AssetCode::native()).await < total_per_keystore {
+    write_output_json(&opt, &children, &transferred);
+
+    // Wait for the children to report the new balances. We only wait for the transfers which were
+    // confirmed; any others failed or timed out, and may never arrive.
+    for ((keystore, _, key), transferred) in children.iter().zip(&transferred) {
+        let expected = U256::from(*transferred);
+        while keystore.balance(&asset).await < expected {
+            eprintln!("Waiting for {} to receive {} tokens", key, expected);
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    // Report any children which did not receive everything allocated to them, even after retries.
+    let mut unfunded = 0;
+    for (((_, _, key), allocation), transferred) in
+        children.iter().zip(&allocations).zip(&transferred)
+    {
+        if U256::from(*transferred) < allocation.total() {
             eprintln!(
-                "Waiting for {} to receive {} tokens",
-                key, total_per_keystore
+                "Error: {} received only {} of {} units",
+                key,
+                transferred,
+                allocation.total()
             );
-            async_std::task::sleep(Duration::from_secs(1)).await;
+            unfunded += 1;
         }
     }
+    if unfunded > 0 {
+        eprintln!(
+            "{} of {} keystores were not fully funded",
+            unfunded,
+            children.len()
+        );
+        exit(1);
+    }
 }
@@ -12,6 +12,7 @@ use clap::Parser;
 use cld::ClDuration;
 use dirs::data_local_dir;
 use espresso_core::kv_merkle_tree::KVMerkleTree;
+use espresso_core::lw_persistence::LWPersistenceError;
 use espresso_core::reward::{
     eligibility, CollectRewardNote, CollectedRewards, CollectedRewardsSet,
 };
@@ -645,10 +646,20 @@ async fn init_hotshot(
     } else {
         debug!("Restoring from persisted session");
         let lw_persistence = LWPersistence::load(storage_path, "validator").unwrap();
-        if lw_persistence.load_latest_leaf().is_ok() {
-            panic!("unimplemented") // TODO not storing CollectedRewardSet yet + catchup not implemented
-        } else {
-            (lw_persistence, CollectedRewardsSet::EmptySubtree)
+        match lw_persistence.load_latest_leaf() {
+            Ok(_) => {
+                panic!("unimplemented") // TODO not storing CollectedRewardSet yet + catchup not implemented
+            }
+            Err(err @ LWPersistenceError::CorruptedState { .. }) => {
+                // Starting from genesis would silently discard the corrupted state. Make the
+                // operator decide what to do with it instead.
+                panic!(
+                    "cannot restore from {}: {}. Move the store aside or restart with --reset-store-state.",
+                    storage_path.display(),
+                    err
+                )
+            }
+            Err(_) => (lw_persistence, CollectedRewardsSet::EmptySubtree),
         }
     };
     let stake_table_list = genesis.stake_table.clone();
@@ -892,8 +903,8 @@ async fn collect_reward_daemon<R: CryptoRng + RngCore + Send>(
                 // 0. check if I'm elected
 
                 if let Some(vrf_proof) = eligibility::prove_eligibility(
-                    validator_state.chain.committee_size,
-                    validator_state.chain.vrf_seed,
+                    validator_state.chain().committee_size,
+                    validator_state.chain().vrf_seed,
                     view_number,
                     &staking_priv_key,
                     stake_amount,
@@ -912,7 +923,7 @@ async fn collect_reward_daemon<R: CryptoRng + RngCore + Send>(
                         validator_state
                             .historical_stake_tables_commitment
                             .num_leaves,
-                        validator_state.chain.committee_size,
+                        validator_state.chain().committee_size,
                         validator_state.block_height,
                         &staking_priv_key,
                         cap_pub_key.clone(),
@@ -925,6 +936,7 @@ async fn collect_reward_daemon<R: CryptoRng + RngCore + Send>(
                         txn: EspressoTransaction::Reward(Box::new(note)),
                         proofs: EspressoTxnHelperProofs::Reward(Box::new(proof)),
                         memos: None,
+                        memo: None,
                     };
 
                     // 2. submit transaction